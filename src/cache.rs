@@ -1,35 +1,102 @@
 use std::path::Path;
 
-use cacache;
+use serde_json::{json, Value};
+
+// disk cache store shared by the page and HTTP caches
+pub static CACHE_DIR: &str = "./gisture_cache";
 
 #[derive(Debug, Clone)]
 pub(crate) struct Cache {
     pub permalink_key: String,
-    pub updated_at: String,
+    // hash of everything a page is rendered from
+    pub fingerprint: String,
+}
+
+/// A cached HTTP response body along with its validators
+#[derive(Debug, Clone)]
+pub(crate) struct HttpCache {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub link: Option<String>,
+    pub body: Vec<u8>,
 }
 
 // NOTE: cache function calls are blocking (forced sync)
 impl Cache {
     /// Save a blog entry to disk cache
     pub async fn save_cache_entry(&self) -> Result<(), cacache::Error> {
-        let cache_dir = String::from("./gisture_cache");
-
-        cacache::write(&cache_dir, &self.permalink_key, self.updated_at.as_bytes()).await?;
+        cacache::write(CACHE_DIR, &self.permalink_key, self.fingerprint.as_bytes()).await?;
 
         Ok(())
     }
 
     /// Check if a blog entry is cached on disk
     pub async fn is_cached(&self) -> Result<bool, cacache::Error> {
-        let cache_dir = String::from("./gisture_cache");
-        let data = cacache::read(&cache_dir, &self.permalink_key).await?;
+        let data = cacache::read(CACHE_DIR, &self.permalink_key).await?;
 
         let build_file = Path::new(&format!("public/{}", &self.permalink_key)).exists();
 
-        if data == self.updated_at.as_bytes() && build_file {
+        if data == self.fingerprint.as_bytes() && build_file {
             Ok(true)
         } else {
             Ok(false)
         }
     }
 }
+
+impl HttpCache {
+    /// HTTP responses are keyed by URL, prefixed to not collide with permalinks
+    fn cache_key(url: &str) -> String {
+        format!("http:{}", url)
+    }
+
+    /// Save a response body and its validators to disk cache
+    pub async fn save_response(&self) -> Result<(), cacache::Error> {
+        // bodies are content-addressed, the URL key only points to them
+        let integrity = cacache::write_hash(CACHE_DIR, &self.body).await?;
+
+        let record = json!({
+            "etag": self.etag,
+            "last_modified": self.last_modified,
+            "link": self.link,
+            "integrity": integrity.to_string()
+        });
+
+        cacache::write(CACHE_DIR, Self::cache_key(&self.url), record.to_string()).await?;
+
+        Ok(())
+    }
+
+    /// Load a previously saved response, if there is one
+    pub async fn load_response(url: &str) -> Result<Option<Self>, cacache::Error> {
+        let record = match cacache::read(CACHE_DIR, Self::cache_key(url)).await {
+            Ok(record) => record,
+            Err(cacache::Error::EntryNotFound(_, _)) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        let record: Value = match serde_json::from_slice(&record) {
+            Ok(record) => record,
+            // a corrupted record is as good as a cache miss
+            Err(_) => return Ok(None),
+        };
+
+        let integrity = match record["integrity"].as_str() {
+            Some(integrity) => integrity.parse()?,
+            None => return Ok(None),
+        };
+
+        let body = cacache::read_hash(CACHE_DIR, &integrity).await?;
+
+        let header = |name: &str| record[name].as_str().map(String::from);
+
+        Ok(Some(Self {
+            url: url.to_string(),
+            etag: header("etag"),
+            last_modified: header("last_modified"),
+            link: header("link"),
+            body,
+        }))
+    }
+}
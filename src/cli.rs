@@ -15,5 +15,9 @@ pub fn interface() -> ArgMatches<'static> {
             .value_name("PORT")
             .required(false)
             .index(2))
+        .arg(Arg::with_name("OFFLINE")
+            .help("Build from the gists cached by previous builds without contacting GitHub.")
+            .long("offline")
+            .required(false))
         .get_matches()
 }
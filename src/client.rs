@@ -0,0 +1,401 @@
+use std::fmt;
+use std::io::Read;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ureq::{Agent, AgentBuilder, Error, Request, Response};
+
+use futures::executor::block_on;
+use serde_json::Value;
+
+use crate::{
+    cache::HttpCache,
+    config::Config,
+    messages::{push_message, Type},
+};
+
+// longest we're willing to sleep for a rate limit reset before giving up
+static MAX_WAIT_SECS: u64 = 15 * 60;
+
+// upper bound for the exponential backoff between retries
+static MAX_BACKOFF_SECS: u64 = 60;
+
+/// What to do after a failed request
+#[derive(Debug, PartialEq)]
+enum Retry {
+    After(Duration),
+    Abort(String),
+}
+
+/// Why a request has no response to work with
+#[derive(Debug)]
+pub(crate) enum FetchError {
+    // offline, and the URL was never cached
+    NotCached,
+    // the request failed for good (after retrying what's worth retrying)
+    Failed(String),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::NotCached => write!(f, "not in the disk cache yet"),
+            FetchError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// A ureq agent (connection pool) that retries rate limited and transient failures
+#[derive(Clone)]
+pub(crate) struct GistClient {
+    conn_pool: Agent,
+    token: Option<String>,
+    max_retries: u32,
+    offline: bool,
+}
+
+impl GistClient {
+    pub fn new(config: &Config, offline: bool) -> Self {
+        // register a ureq agent (connection pool)
+        let conn_pool: Agent = AgentBuilder::new()
+            .timeout_read(Duration::from_secs(5))
+            .timeout_write(Duration::from_secs(5))
+            .build();
+
+        Self {
+            conn_pool,
+            token: config.github_token.clone(),
+            max_retries: config.max_retries,
+            offline,
+        }
+    }
+
+    /// Authenticate a request if a GitHub token is configured
+    fn authorize(&self, request: Request) -> Request {
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("token {}", token)),
+            None => request,
+        }
+    }
+
+    /// Prepare a GET request, authenticated if a GitHub token is configured
+    fn request(&self, url: &str, cached: Option<&HttpCache>) -> Request {
+        let mut request = self.authorize(
+            self.conn_pool
+                .get(url)
+                .set("Accept", "application/vnd.github.v3+json"),
+        );
+
+        // conditional request, a `304 Not Modified` doesn't count against the rate limit
+        if let Some(cached) = cached {
+            if let Some(etag) = &cached.etag {
+                request = request.set("If-None-Match", etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.set("If-Modified-Since", last_modified);
+            }
+        }
+
+        request
+    }
+
+    /// Fetch a URL, served from the disk cache if it hasn't been modified
+    pub fn fetch(&self, url: &str) -> Result<HttpCache, FetchError> {
+        let cached = block_on(HttpCache::load_response(url)).ok().flatten();
+
+        // offline builds never touch the network
+        if self.offline {
+            return cached.ok_or(FetchError::NotCached);
+        }
+
+        let response = self.send(url, || {
+            self.request(url, cached.as_ref()).call().map_err(Box::new)
+        })?;
+
+        if response.status() == 304 {
+            if let Some(cached) = cached {
+                return Ok(cached);
+            }
+        }
+
+        Self::save(url, response)
+    }
+
+    /// Send a GraphQL query, the response is cached under the query itself
+    pub fn query(&self, url: &str, query: &Value) -> Result<HttpCache, FetchError> {
+        let cache_key = format!("{}#{}", url, query);
+
+        if self.offline {
+            return block_on(HttpCache::load_response(&cache_key))
+                .ok()
+                .flatten()
+                .ok_or(FetchError::NotCached);
+        }
+
+        let response = self.send(url, || {
+            self.authorize(self.conn_pool.post(url))
+                .send_json(query.clone())
+                .map_err(Box::new)
+        })?;
+
+        Self::save(&cache_key, response)
+    }
+
+    /// Read a response into the disk cache
+    fn save(url: &str, response: Response) -> Result<HttpCache, FetchError> {
+        let header = |name: &str| response.header(name).map(String::from);
+        let (etag, last_modified, link) = (header("ETag"), header("Last-Modified"), header("Link"));
+
+        let mut body: Vec<u8> = Vec::new();
+        if let Err(error) = response.into_reader().read_to_end(&mut body) {
+            return Err(FetchError::Failed(format!(
+                "failed while reading the response: {}",
+                error
+            )));
+        }
+
+        let fetched = HttpCache {
+            url: url.to_string(),
+            etag,
+            last_modified,
+            link,
+            body,
+        };
+
+        // cached even without validators, offline builds are served from here
+        if let Err(error) = block_on(fetched.save_response()) {
+            let message = format!("Failed to cache response of `{}`: \n\t{}", url, error);
+            push_message(Type::Warning, &message);
+        }
+
+        Ok(fetched)
+    }
+
+    /// Send a request, waiting out rate limits and backing off on transient errors
+    fn send<F>(&self, url: &str, call: F) -> Result<Response, FetchError>
+    where
+        F: Fn() -> Result<Response, Box<Error>>,
+    {
+        let mut attempt: u32 = 0;
+
+        loop {
+            let error = match call() {
+                Ok(response) => return Ok(response),
+                Err(error) => *error,
+            };
+
+            let retry = match error {
+                Error::Status(status, response) => Self::retry_status(status, &response, attempt),
+                Error::Transport(transport) => {
+                    let message =
+                        format!("Couldn't send HTTP request to Gist API: \n\t{}", transport);
+                    push_message(Type::Warning, &message);
+                    Retry::After(Self::backoff(attempt))
+                }
+            };
+
+            match retry {
+                Retry::After(wait) if attempt < self.max_retries => {
+                    attempt += 1;
+                    let message = format!(
+                        "Retrying `{}` in {}s (attempt {} of {}).",
+                        url,
+                        wait.as_secs(),
+                        attempt,
+                        self.max_retries
+                    );
+                    push_message(Type::Warning, &message);
+                    thread::sleep(wait)
+                }
+                Retry::After(_) => {
+                    return Err(FetchError::Failed(format!(
+                        "Gave up on `{}` after {} retries, check your network connection \
+                        or raise `max_retries` in `gisture.json`.",
+                        url, self.max_retries
+                    )))
+                }
+                Retry::Abort(reason) => {
+                    return Err(FetchError::Failed(format!(
+                        "Gist API request to `{}` failed: {}",
+                        url, reason
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Decide whether an HTTP error status is worth retrying
+    fn retry_status(status: u16, response: &Response, attempt: u32) -> Retry {
+        match status {
+            403 | 429 => {
+                // secondary rate limits come with a `Retry-After` (in seconds)
+                if let Some(seconds) = Self::header_secs(response, "Retry-After") {
+                    let message = format!("Hit a secondary rate limit (HTTP {}).", status);
+                    push_message(Type::Warning, &message);
+                    return Self::wait_for(seconds);
+                }
+
+                // primary rate limit, wait until the window resets
+                if response.header("X-RateLimit-Remaining") == Some("0") {
+                    let reset_at = Self::header_secs(response, "X-RateLimit-Reset").unwrap_or(0);
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|now| now.as_secs())
+                        .unwrap_or(0);
+                    push_message(Type::Warning, "Gist API rate limit exhausted.");
+                    return Self::wait_for(reset_at.saturating_sub(now) + 1);
+                }
+
+                if status == 429 {
+                    Retry::After(Self::backoff(attempt))
+                } else {
+                    Retry::Abort(
+                        "HTTP 403 Forbidden, make sure `github_token` is valid and has the `gist` scope."
+                            .into(),
+                    )
+                }
+            }
+            500 | 502 | 503 | 504 => {
+                let message = format!("Gist API responded with HTTP {}.", status);
+                push_message(Type::Warning, &message);
+                Retry::After(Self::backoff(attempt))
+            }
+            _ => Retry::Abort(format!("HTTP {} {}", status, response.status_text())),
+        }
+    }
+
+    /// Wait for the given seconds unless it's unreasonably long
+    fn wait_for(seconds: u64) -> Retry {
+        if seconds > MAX_WAIT_SECS {
+            Retry::Abort(format!(
+                "Rate limit resets in {} minutes, set `github_token` in `gisture.json` \
+                (or `GISTURE_GITHUB_TOKEN`) for a higher limit or try again later.",
+                seconds / 60 + 1
+            ))
+        } else {
+            Retry::After(Duration::from_secs(seconds))
+        }
+    }
+
+    /// Exponential backoff: 1s, 2s, 4s... capped at `MAX_BACKOFF_SECS`
+    fn backoff(attempt: u32) -> Duration {
+        let seconds = 2u64.saturating_pow(attempt).min(MAX_BACKOFF_SECS);
+        Duration::from_secs(seconds)
+    }
+
+    /// Parse a header holding a number of seconds (or a UNIX timestamp)
+    fn header_secs(response: &Response, header: &str) -> Option<u64> {
+        response.header(header)?.trim().parse::<u64>().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status_line: &str, headers: &[(&str, String)]) -> Response {
+        let headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        format!("HTTP/1.1 {}\r\n{}\r\n", status_line, headers)
+            .parse()
+            .unwrap()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn retry(status_line: &str, headers: &[(&str, String)], attempt: u32) -> Retry {
+        let response = response(status_line, headers);
+        GistClient::retry_status(response.status(), &response, attempt)
+    }
+
+    #[test]
+    fn waits_for_retry_after() {
+        let headers = [("Retry-After", String::from("30"))];
+
+        assert_eq!(
+            retry("403 Forbidden", &headers, 0),
+            Retry::After(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry("429 Too Many Requests", &headers, 3),
+            Retry::After(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn waits_for_a_close_rate_limit_reset() {
+        let headers = [
+            ("X-RateLimit-Remaining", String::from("0")),
+            ("X-RateLimit-Reset", (now() + 10).to_string()),
+        ];
+
+        match retry("403 Forbidden", &headers, 0) {
+            Retry::After(wait) => assert!((10..=11).contains(&wait.as_secs())),
+            retry => panic!("expected a wait, got {:?}", retry),
+        }
+    }
+
+    #[test]
+    fn gives_up_on_a_distant_rate_limit_reset() {
+        let headers = [
+            ("X-RateLimit-Remaining", String::from("0")),
+            (
+                "X-RateLimit-Reset",
+                (now() + MAX_WAIT_SECS + 120).to_string(),
+            ),
+        ];
+
+        match retry("403 Forbidden", &headers, 0) {
+            Retry::Abort(reason) => assert!(reason.contains("set `github_token`")),
+            retry => panic!("expected to give up, got {:?}", retry),
+        }
+    }
+
+    #[test]
+    fn gives_up_on_forbidden_without_rate_limit_headers() {
+        match retry("403 Forbidden", &[], 0) {
+            Retry::Abort(reason) => assert!(reason.contains("`gist` scope")),
+            retry => panic!("expected to give up, got {:?}", retry),
+        }
+        assert_eq!(
+            retry("404 Not Found", &[], 0),
+            Retry::Abort(String::from("HTTP 404 Not Found"))
+        );
+    }
+
+    #[test]
+    fn backs_off_on_server_errors() {
+        assert_eq!(
+            retry("502 Bad Gateway", &[], 0),
+            Retry::After(Duration::from_secs(1))
+        );
+        assert_eq!(
+            retry("503 Service Unavailable", &[], 3),
+            Retry::After(Duration::from_secs(8))
+        );
+        assert_eq!(
+            retry("429 Too Many Requests", &[], 2),
+            Retry::After(Duration::from_secs(4))
+        );
+    }
+
+    #[test]
+    fn caps_the_backoff() {
+        assert_eq!(GistClient::backoff(5), Duration::from_secs(32));
+        assert_eq!(
+            GistClient::backoff(6),
+            Duration::from_secs(MAX_BACKOFF_SECS)
+        );
+        assert_eq!(
+            GistClient::backoff(100),
+            Duration::from_secs(MAX_BACKOFF_SECS)
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::env;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
@@ -5,36 +7,102 @@ use std::path::Path;
 use std::process::exit;
 
 use serde::{Deserialize, Serialize};
+use syntect::highlighting::Theme;
 
-use crate::messages::{push_message, Type};
+use crate::{
+    messages::{push_message, redact_secret, Type},
+    parsers::ParserUtils,
+    table::{CsvHeader, Table},
+};
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub(crate) struct Config {
     pub github_username: String,
+    pub api_base_url: String,
     pub blog_title: String,
     pub blog_description: String,
     pub blog_url: String,
     pub pages_title: String,
     pub minify_html: bool,
     pub show_comments: bool,
+    pub show_revisions: bool,
+    pub max_revisions: usize,
+    pub gists_per_page: u8,
+    pub github_token: Option<String>,
+    pub max_retries: u32,
+    pub max_concurrent_downloads: usize,
+    pub content_source: String,
+    pub content_dir: String,
+    pub pinned_revisions: HashMap<String, String>,
+    pub index_order: String,
+    pub csv_header: String,
+    pub csv_max_rows: usize,
+    pub strip_title: bool,
+    pub syntax_highlighting: String,
+    pub syntax_theme: String,
+    pub syntax_dark_theme: Option<String>,
+    pub heading_anchors: bool,
+    pub words_per_minute: usize,
+    pub math: bool,
+}
+
+/// Options of `gisture.json` which change how blogs are rendered, checked once per build
+#[derive(Clone, Copy)]
+pub(crate) struct RenderOptions {
+    pub strip_title: bool,
+    pub words_per_minute: usize,
+    pub csv_header: CsvHeader,
+    pub csv_max_rows: usize,
+    // code is highlighted with classes (`syntax.css`) instead of inline styles
+    pub class_highlighting: bool,
+    pub syntax_theme: &'static Theme,
+    pub syntax_dark_theme: Option<&'static Theme>,
+    pub heading_anchors: bool,
+    pub math: bool,
 }
 
 static CONFIG_FILE: &str = "gisture.json";
 
-impl Config {
-    pub fn default() -> Self {
+// environment variable which takes precedence over `github_token`
+static TOKEN_ENV_VAR: &str = "GISTURE_GITHUB_TOKEN";
+
+impl Default for Config {
+    fn default() -> Self {
         // default config file values
         Self {
             github_username: "octocat".into(),
+            api_base_url: "https://api.github.com".into(),
             blog_title: "John Wick's Pencil".into(),
             blog_description: "Rusty, I Guess.".into(),
             blog_url: "https://blog.johnwickspencil.com/".into(),
             pages_title: "{{ blog_title }} | John Wick's Pencil".into(),
             minify_html: false,
             show_comments: true,
+            show_revisions: false,
+            max_revisions: 10,
+            gists_per_page: 100,
+            github_token: None,
+            max_retries: 5,
+            max_concurrent_downloads: 8,
+            content_source: "gist".into(),
+            content_dir: "content".into(),
+            pinned_revisions: HashMap::new(),
+            index_order: "date".into(),
+            csv_header: "auto".into(),
+            csv_max_rows: 1000,
+            strip_title: false,
+            syntax_highlighting: "inline".into(),
+            syntax_theme: "InspiredGitHub".into(),
+            syntax_dark_theme: None,
+            heading_anchors: false,
+            words_per_minute: 200,
+            math: false,
         }
     }
+}
 
+impl Config {
     /// Generate a boilerplate config file
     pub fn generate_default() {
         if !Path::new(CONFIG_FILE).exists() {
@@ -87,7 +155,7 @@ impl Config {
             }
         }
 
-        let config: Config = match serde_json::from_str(&user_config) {
+        let mut config: Config = match serde_json::from_str(&user_config) {
             Ok(config) => config,
             Err(error) => {
                 let message = format!("Failed while serializing user config: \n\t{}", error);
@@ -96,6 +164,80 @@ impl Config {
             }
         };
 
+        config.github_token = Self::github_token(config.github_token, env::var(TOKEN_ENV_VAR).ok());
+
+        if let Some(token) = &config.github_token {
+            redact_secret(token);
+        }
+
         config
     }
+
+    /// Options which change how blogs are rendered (an unknown mode or theme ends the build)
+    pub fn render_options(&self) -> RenderOptions {
+        // a theme is checked even if no blog has code to highlight yet
+        let theme = |option: &str, name: &str| match ParserUtils::theme(option, name) {
+            Ok(theme) => theme,
+            Err(message) => {
+                push_message(Type::Error, &message);
+                exit(1)
+            }
+        };
+
+        RenderOptions {
+            strip_title: self.strip_title,
+            words_per_minute: self.words_per_minute.max(1),
+            csv_header: Table::header_mode(&self.csv_header),
+            csv_max_rows: self.csv_max_rows,
+            class_highlighting: ParserUtils::class_highlighting(&self.syntax_highlighting),
+            syntax_theme: theme("syntax_theme", &self.syntax_theme),
+            syntax_dark_theme: self
+                .syntax_dark_theme
+                .as_ref()
+                .map(|dark_theme| theme("syntax_dark_theme", dark_theme)),
+            heading_anchors: self.heading_anchors,
+            math: self.math,
+        }
+    }
+
+    /// The token from `GISTURE_GITHUB_TOKEN`, or else from `github_token` (an empty or blank
+    /// token means anonymous access)
+    fn github_token(config_token: Option<String>, env_token: Option<String>) -> Option<String> {
+        env_token
+            .into_iter()
+            .chain(config_token)
+            .map(|token| token.trim().to_string())
+            .find(|token| !token.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_the_token_from_the_environment() {
+        let token = Config::github_token(Some("from-config".into()), Some(" from-env\n".into()));
+
+        assert_eq!(token.as_deref(), Some("from-env"));
+    }
+
+    #[test]
+    fn falls_back_to_the_token_from_the_config_file() {
+        let token = Config::github_token(Some("from-config".into()), Some("  ".into()));
+        assert_eq!(token.as_deref(), Some("from-config"));
+
+        let token = Config::github_token(Some("from-config".into()), None);
+        assert_eq!(token.as_deref(), Some("from-config"));
+    }
+
+    #[test]
+    fn treats_blank_tokens_as_anonymous_access() {
+        assert_eq!(Config::github_token(Some(String::new()), None), None);
+        assert_eq!(
+            Config::github_token(Some(" \t".into()), Some(String::new())),
+            None
+        );
+        assert_eq!(Config::github_token(None, None), None);
+    }
 }
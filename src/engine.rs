@@ -6,6 +6,7 @@ use crate::{
     messages::{push_message, Type},
     metadata::Utils,
     serve,
+    source::{ContentSource, LocalSource},
     template::TemplateWriter,
 };
 
@@ -23,10 +24,33 @@ pub async fn init(args: ArgMatches<'_>) {
 
     match feature {
         Some("build") => {
-            let page_map = GistApi::get_all_blogs();
+            let offline = args.is_present("OFFLINE");
             let config = Config::get_config();
+            // checked before anything is fetched, a typo shouldn't waste a whole build
+            let options = config.render_options();
 
-            TemplateWriter::render_templates(&page_map);
+            let page_map = match config.content_source.as_str() {
+                "gist" => GistApi { offline, options }.get_all_blogs().await,
+                "local" => {
+                    LocalSource {
+                        content_dir: config.content_dir.clone(),
+                        options,
+                    }
+                    .get_all_blogs()
+                    .await
+                }
+                source => {
+                    let message = format!(
+                        "Unknown `content_source` \"{}\" (expected \"gist\" or \"local\").",
+                        source
+                    );
+                    push_message(Type::Error, &message);
+                    exit(1)
+                }
+            };
+
+            // offline builds are usually template tweaks, so every page is rendered again
+            TemplateWriter::render_templates(&page_map, &options, offline);
             Utils::generate_site_metadata(&page_map, &config);
 
             push_message(Type::Success, "Your gisture blog is ready to ship. 🚀")
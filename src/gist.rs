@@ -1,14 +1,20 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::process::exit;
-use std::time::Duration;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
 
-use serde_json::{Map, Value};
-use ureq::{Agent, AgentBuilder};
+use futures::stream::{self, StreamExt};
+use serde_json::{json, Map, Value};
+use tokio::task;
 
 use crate::{
-    config::Config,
+    cache::CACHE_DIR,
+    client::{FetchError, GistClient},
+    config::{Config, RenderOptions},
     messages::{push_message, Type},
     parsers::ParserUtils,
+    source::{BlogEntry, ContentSource},
 };
 
 #[derive(Debug, Clone)]
@@ -18,41 +24,361 @@ pub(crate) struct GistPage {
     pub created_at: String,
     pub updated_at: String,
     pub content: String,
+    // table of contents of the content's headings
+    pub toc: String,
+    pub word_count: usize,
+    // estimated minutes to read the content
+    pub reading_time: usize,
+    pub excerpt: String,
     pub html_url: String,
+    pub revision: Option<String>,
+    pub revisions: Vec<Revision>,
+    pub comments: Vec<Comment>,
+    pub star_count: u64,
+    pub fork_count: u64,
+    pub attachments: Vec<Attachment>,
+    pub tags: Vec<String>,
+    pub cover_image: Option<String>,
+    pub layout: Option<String>,
+    pub extra: HashMap<String, String>,
 }
 
-pub(crate) struct GistApi;
+/// Per-blog overrides from the metadata block on top of the markdown
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PageMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub slug: Option<String>,
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+    pub cover_image: Option<String>,
+    pub draft: bool,
+    pub layout: Option<String>,
+    // any other key, for the templates
+    pub extra: HashMap<String, String>,
+}
+
+/// A sibling file of a blog inside its gist (code samples, data files, images...)
+#[derive(Debug, Clone)]
+pub(crate) struct Attachment {
+    pub file: String,
+    pub content: Vec<u8>,
+}
+
+/// A comment on the gist of a blog
+#[derive(Debug, Clone)]
+pub(crate) struct Comment {
+    pub user: String,
+    pub user_url: String,
+    pub avatar_url: String,
+    pub created_at: String,
+    pub html_url: String,
+    pub content: String,
+}
+
+/// A change made to a blog in one of its gist's revisions
+#[derive(Debug, Clone)]
+pub(crate) struct Revision {
+    pub version: String,
+    pub committed_at: String,
+    pub html_url: String,
+    pub diff: String,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+// raw URLs serve files up to 10 MB, anything larger has to be cloned
+static RAW_SIZE_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// A non-gisture file inside a gist, waiting to be downloaded
+#[derive(Clone)]
+struct AttachmentFile {
+    file: String,
+    raw_url: Option<String>,
+    // set when the file is too large for its raw URL and read from a git clone
+    local_path: Option<PathBuf>,
+}
+
+/// A gisture file inside a gist, waiting to be downloaded
+struct BlogFile {
+    file: String,
+    raw_url: Option<String>,
+    // size reported by the Gist API, to detect partial downloads
+    size: u64,
+    // set when the file is read from a git clone of the gist instead
+    local_path: Option<PathBuf>,
+    // API URL of the gist, revisions live under it
+    gist_url: String,
+    // SHA of the pinned revision and when it was committed
+    revision: Option<String>,
+    revised_at: Option<String>,
+    // changelog of the file, newest first
+    revisions: Vec<Revision>,
+    comments: Vec<Comment>,
+    star_count: u64,
+    // `None` until counted through the REST API (without a token)
+    fork_count: Option<u64>,
+    // the other files of the gist and their downloaded contents
+    attachment_files: Vec<AttachmentFile>,
+    attachments: Vec<Attachment>,
+    gist: Value,
+}
+
+// stars and forks of every gist in a single query, only GraphQL knows about stars
+static GIST_STATS_QUERY: &str = "query($login: String!, $after: String) {
+  user(login: $login) {
+    gists(first: 100, after: $after, privacy: ALL) {
+      pageInfo { hasNextPage endCursor }
+      nodes { name stargazerCount forks { totalCount } }
+    }
+  }
+}";
+
+/// Blogs published as the configured user's gists
+pub(crate) struct GistApi {
+    pub offline: bool,
+    pub options: RenderOptions,
+}
 
 impl GistApi {
-    /// Fetch user's gist entries
-    fn get_user_gists(conn_pool: &Agent) -> Vec<Value> {
-        let username = Config::get_config().github_username;
-        let endpoint = format!("https://api.github.com/users/{}/gists", username);
-        let user_gists: String = match conn_pool
-            .get(&endpoint)
-            .set("Accept", "application/vnd.github.v3+json")
-            .call()
-        {
-            Ok(response) => match response.into_string() {
-                Ok(json_response) => json_response,
+    /// Full URL of a Gist API endpoint (GitHub Enterprise hosts it under `/api/v3`)
+    fn api_url(config: &Config, path: &str) -> String {
+        ParserUtils::join_url_path(&config.api_base_url, path)
+    }
+
+    /// URL of the GraphQL API (GitHub Enterprise hosts it under `/api/graphql`)
+    fn graphql_url(config: &Config) -> String {
+        let base_url = config.api_base_url.trim_end_matches('/');
+
+        match base_url.strip_suffix("/v3") {
+            Some(api_url) => format!("{}/graphql", api_url),
+            None => format!("{}/graphql", base_url),
+        }
+    }
+
+    /// Fetch the star and fork counts of the user's gists (by gist ID)
+    /// (`None` without a GitHub token, GraphQL doesn't allow anonymous access)
+    fn get_gist_stats(client: &GistClient, config: &Config) -> Option<HashMap<String, (u64, u64)>> {
+        config.github_token.as_ref()?;
+
+        let url = Self::graphql_url(config);
+
+        let mut stats: HashMap<String, (u64, u64)> = HashMap::new();
+        let mut cursor = Value::Null;
+
+        loop {
+            let query = json!({
+                "query": GIST_STATS_QUERY,
+                "variables": { "login": config.github_username, "after": cursor }
+            });
+
+            let response: Value = match client.query(&url, &query) {
+                Ok(response) => serde_json::from_slice(&response.body).unwrap_or_default(),
+                Err(error) => {
+                    let message = format!("Couldn't fetch star and fork counts: \n\t{}", error);
+                    push_message(Type::Warning, &message);
+                    return None;
+                }
+            };
+
+            if let Some(errors) = response.get("errors") {
+                let message = format!("Failed to fetch star and fork counts: \n\t{}", errors);
+                push_message(Type::Warning, &message);
+                return None;
+            }
+
+            let gists = &response["data"]["user"]["gists"];
+
+            for node in gists["nodes"].as_array().into_iter().flatten() {
+                if let Some(gist_id) = node["name"].as_str() {
+                    let star_count = node["stargazerCount"].as_u64().unwrap_or(0);
+                    let fork_count = node["forks"]["totalCount"].as_u64().unwrap_or(0);
+                    stats.insert(gist_id.to_string(), (star_count, fork_count));
+                }
+            }
+
+            if gists["pageInfo"]["hasNextPage"].as_bool() != Some(true) {
+                break;
+            }
+            cursor = gists["pageInfo"]["endCursor"].clone();
+        }
+
+        Some(stats)
+    }
+
+    /// Count the forks of a gist through the REST API
+    fn get_fork_count(client: &GistClient, blog_file: &BlogFile) -> u64 {
+        let forks_url = ParserUtils::join_url_path(&blog_file.gist_url, "forks?per_page=100");
+
+        match Self::get_all_pages(client, &forks_url) {
+            Ok((forks, _)) => forks.len() as u64,
+            Err(error) => {
+                let message = format!(
+                    "Couldn't count the forks of `{}`: \n\t{}",
+                    blog_file.file, error
+                );
+                push_message(Type::Warning, &message);
+                0
+            }
+        }
+    }
+
+    /// Fetch user's gist entries (walks every page of the listing)
+    fn get_user_gists(client: &GistClient) -> Vec<Value> {
+        let config = Config::get_config();
+
+        // the Gist API caps `per_page` at 100
+        let per_page = match config.gists_per_page {
+            1..=100 => config.gists_per_page,
+            _ => {
+                let message = format!(
+                    "`gists_per_page` should be between 1 and 100 (got {}), using 100.",
+                    config.gists_per_page
+                );
+                push_message(Type::Warning, &message);
+                100
+            }
+        };
+
+        let endpoint_path = format!(
+            "users/{}/gists?per_page={}",
+            config.github_username, per_page
+        );
+        let (user_gists, pages_walked) = match Self::get_all_pages(
+            client,
+            &Self::api_url(&config, &endpoint_path),
+        ) {
+            Ok(user_gists) => user_gists,
+            Err(FetchError::NotCached) => {
+                push_message(
+                        Type::Error,
+                        "The gist listing isn't cached yet, run `gisture build` once with network access.",
+                    );
+                exit(1);
+            }
+            Err(FetchError::Failed(reason)) => {
+                let message = format!("Couldn't fetch the gist listing: \n\t{}", reason);
+                push_message(Type::Error, &message);
+                exit(1);
+            }
+        };
+
+        let message = format!(
+            "Walked {} page(s) of the Gist API ({} gists).",
+            pages_walked,
+            user_gists.len()
+        );
+        push_message(Type::Info, &message);
+
+        user_gists
+    }
+
+    /// Fetch every page of a paginated endpoint along with the number of pages walked
+    /// (an error only if not even the first page could be fetched)
+    fn get_all_pages(client: &GistClient, url: &str) -> Result<(Vec<Value>, usize), FetchError> {
+        let mut endpoint = Some(url.to_string());
+
+        let mut entries: Vec<Value> = Vec::new();
+        let mut pages_walked: usize = 0;
+
+        // follow the `Link: <...>; rel="next"` headers until the last page
+        while let Some(page_url) = endpoint {
+            let response = match client.fetch(&page_url) {
+                Ok(response) => response,
+                Err(error) if pages_walked == 0 => return Err(error),
                 Err(error) => {
                     let message = format!(
-                        "Failed while encoding response from Gist API: \n\t{}",
+                        "Couldn't fetch page {} of `{}`, later entries are missing: \n\t{}",
+                        pages_walked + 1,
+                        url,
+                        error
+                    );
+                    push_message(Type::Warning, &message);
+                    break;
+                }
+            };
+
+            endpoint = response
+                .link
+                .as_deref()
+                .and_then(ParserUtils::parse_next_link);
+
+            let page_entries: Vec<Value> = match serde_json::from_slice(&response.body) {
+                Ok(page_entries) => page_entries,
+                Err(error) => {
+                    let message = format!(
+                        "Failed to serialize JSON response from Gist API: \n\t{}",
                         error
                     );
                     push_message(Type::Error, &message);
                     exit(1);
                 }
-            },
+            };
+
+            pages_walked += 1;
+            entries.extend(page_entries);
+        }
+
+        Ok((entries, pages_walked))
+    }
+
+    /// Fetch the comments on a gist, rendered to HTML
+    fn get_gist_comments(
+        client: &GistClient,
+        blog_file: &BlogFile,
+        options: &RenderOptions,
+    ) -> Vec<Comment> {
+        let comments_url = ParserUtils::join_url_path(&blog_file.gist_url, "comments?per_page=100");
+
+        let comments = match Self::get_all_pages(client, &comments_url) {
+            Ok((comments, _)) => comments,
             Err(error) => {
-                let message = format!("Couldn't send HTTP request to Gist API: \n\t{}", error);
-                push_message(Type::Error, &message);
-                exit(1);
+                let message = format!(
+                    "Couldn't fetch the comments of `{}`: \n\t{}",
+                    blog_file.file, error
+                );
+                push_message(Type::Warning, &message);
+                return Vec::new();
             }
         };
 
-        match serde_json::from_str(&user_gists) {
-            Ok(gist_vec) => gist_vec,
+        let html_url = blog_file.gist["html_url"].as_str().unwrap_or_default();
+
+        comments
+            .iter()
+            .map(|comment| {
+                let field = |value: &Value| value.as_str().unwrap_or_default().to_string();
+
+                Comment {
+                    user: field(&comment["user"]["login"]),
+                    user_url: field(&comment["user"]["html_url"]),
+                    avatar_url: field(&comment["user"]["avatar_url"]),
+                    created_at: field(&comment["created_at"]),
+                    html_url: format!("{}#gistcomment-{}", html_url, comment["id"]),
+                    // anyone can comment, so raw HTML and scripts are sanitized
+                    content: ParserUtils::parse_untrusted_markdown_to_html(
+                        field(&comment["body"]),
+                        options,
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    /// Revision SHA a gisture file is pinned to in `gisture.json` (keyed by permalink)
+    fn pinned_revision(config: &Config, file: &str) -> Option<String> {
+        config
+            .pinned_revisions
+            .get(&BlogEntry::permalink(file))
+            .cloned()
+    }
+
+    /// Fetch a single gist (lists up to 300 files unlike the user's gist listing)
+    fn get_gist(client: &GistClient, gist_url: &str) -> Result<Value, FetchError> {
+        let response = client.fetch(gist_url)?;
+
+        match serde_json::from_slice(&response.body) {
+            Ok(gist) => Ok(gist),
             Err(error) => {
                 let message = format!(
                     "Failed to serialize JSON response from Gist API: \n\t{}",
@@ -64,110 +390,711 @@ impl GistApi {
         }
     }
 
-    /// Fetch raw markdown for a particular gist entry
-    fn get_gist_markdown(conn_pool: &Agent, url: &str) -> String {
-        match conn_pool.get(url).call() {
-            Ok(response) => match response.into_string() {
-                Ok(markdown) => markdown,
+    /// Parse the files map of a gist
+    fn get_gist_files(gist: &Value) -> Map<String, Value> {
+        match serde_json::from_str(&gist["files"].to_string()) {
+            Ok(gist_files) => gist_files,
+            Err(error) => {
+                let message = format!("Failed while scraping files inside Gist: \n\t{}", error);
+                push_message(Type::Error, &message);
+                exit(1);
+            }
+        }
+    }
+
+    /// Fetch a URL the blog can't be built without, only a failed request ends the build
+    /// (`None` when offline and the URL was never cached)
+    fn fetch_required(client: &GistClient, url: &str) -> Option<Vec<u8>> {
+        match client.fetch(url) {
+            Ok(response) => Some(response.body),
+            Err(FetchError::NotCached) => None,
+            Err(FetchError::Failed(reason)) => {
+                push_message(Type::Error, &reason);
+                exit(1);
+            }
+        }
+    }
+
+    /// Clone (or update) the git repository of a gist into the disk cache, once per build
+    async fn clone_gist(
+        clones: &mut HashMap<String, Option<PathBuf>>,
+        gist: &Value,
+        offline: bool,
+    ) -> Option<PathBuf> {
+        let gist_id = gist["id"].as_str()?;
+
+        if let Some(clone_dir) = clones.get(gist_id) {
+            return clone_dir.clone();
+        }
+
+        let clone_dir = Self::run_git_clone(gist, offline).await;
+        clones.insert(gist_id.to_string(), clone_dir.clone());
+        clone_dir
+    }
+
+    /// Run `git clone` (or `git pull` on an earlier clone) for a gist
+    async fn run_git_clone(gist: &Value, offline: bool) -> Option<PathBuf> {
+        let gist_id = gist["id"].as_str()?;
+        let pull_url = gist["git_pull_url"].as_str()?.to_string();
+
+        let clone_dir = Path::new(CACHE_DIR).join("git").join(gist_id);
+
+        // offline builds can only use what was cloned before
+        if offline {
+            return Some(clone_dir).filter(|clone_dir| clone_dir.exists());
+        }
+
+        // git blocks until the whole repository is transferred
+        let git_dir = clone_dir.clone();
+        let status = task::spawn_blocking(move || {
+            if git_dir.join(".git").exists() {
+                Command::new("git")
+                    .arg("-C")
+                    .arg(&git_dir)
+                    .args(["pull", "--ff-only", "--quiet"])
+                    .status()
+            } else {
+                Command::new("git")
+                    .args(["clone", "--quiet", "--depth", "1", &pull_url])
+                    .arg(&git_dir)
+                    .status()
+            }
+        })
+        .await;
+
+        match status {
+            Ok(Ok(status)) if status.success() => Some(clone_dir),
+            Ok(Ok(status)) => {
+                let message = format!("Failed to clone gist `{}` ({}).", gist_id, status);
+                push_message(Type::Warning, &message);
+                None
+            }
+            Ok(Err(error)) => {
+                let message = format!(
+                    "Failed to run `git` to clone gist `{}` due to: \n\t{}",
+                    gist_id, error
+                );
+                push_message(Type::Warning, &message);
+                None
+            }
+            Err(error) => {
+                let message = format!("Failed while cloning gists: \n\t{}", error);
+                push_message(Type::Error, &message);
+                exit(1);
+            }
+        }
+    }
+
+    /// Fetch the markdown of a gisture file at a pinned gist revision
+    fn get_revision_markdown(
+        client: &GistClient,
+        blog_file: &mut BlogFile,
+        revision: &str,
+    ) -> Option<String> {
+        let revision_url = ParserUtils::join_url_path(&blog_file.gist_url, revision);
+
+        let gist_revision: Value = match Self::fetch_required(client, &revision_url) {
+            Some(body) => serde_json::from_slice(&body).ok()?,
+            None => {
+                let message = format!(
+                    "Revision `{}` of `{}` isn't cached.",
+                    revision, blog_file.file
+                );
+                push_message(Type::Warning, &message);
+                return None;
+            }
+        };
+
+        let metadata = &gist_revision["files"][&blog_file.file];
+
+        let raw_url = match metadata["raw_url"].as_str() {
+            Some(raw_url) => raw_url,
+            None => {
+                let message = format!(
+                    "`{}` doesn't exist in the pinned revision `{}`.",
+                    blog_file.file, revision
+                );
+                push_message(Type::Warning, &message);
+                return None;
+            }
+        };
+
+        let markdown_content = String::from_utf8(Self::fetch_required(client, raw_url)?).ok()?;
+
+        // attachments are pinned along with the blog
+        blog_file.attachment_files =
+            Self::get_attachment_files(&Self::get_gist_files(&gist_revision), None);
+
+        blog_file.size = metadata["size"].as_u64().unwrap_or(0);
+        blog_file.revision = Some(revision.to_string());
+        blog_file.revised_at = gist_revision["history"][0]["committed_at"]
+            .as_str()
+            .map(String::from);
+
+        Some(markdown_content)
+    }
+
+    /// Fetch the content of a file at a gist revision (empty if it didn't exist yet)
+    fn get_revision_content(
+        client: &GistClient,
+        gist_url: &str,
+        revision: &str,
+        file: &str,
+    ) -> Result<String, FetchError> {
+        let gist_revision =
+            Self::get_gist(client, &ParserUtils::join_url_path(gist_url, revision))?;
+
+        match gist_revision["files"][file]["raw_url"].as_str() {
+            Some(raw_url) => String::from_utf8(client.fetch(raw_url)?.body)
+                .map_err(|error| FetchError::Failed(error.to_string())),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Build the changelog of a gisture file from its gist's revision history
+    fn get_revision_history(
+        client: &GistClient,
+        blog_file: &BlogFile,
+        max_revisions: usize,
+    ) -> Vec<Revision> {
+        let history: Vec<Value> = match Self::get_gist(client, &blog_file.gist_url) {
+            Ok(gist) => match gist["history"].as_array() {
+                Some(history) => history.clone(),
+                None => return Vec::new(),
+            },
+            Err(error) => {
+                let message = format!(
+                    "Couldn't fetch the revision history of `{}`: \n\t{}",
+                    blog_file.file, error
+                );
+                push_message(Type::Warning, &message);
+                return Vec::new();
+            }
+        };
+
+        // newest first, revisions after a pinned one aren't approved yet
+        let mut history: &[Value] = &history;
+        if let Some(pinned) = &blog_file.revision {
+            if let Some(position) = history
+                .iter()
+                .position(|entry| entry["version"].as_str() == Some(pinned.as_str()))
+            {
+                history = &history[position..];
+            }
+        }
+
+        // the revision before the oldest one shown is only needed to diff against
+        let (shown, baseline) = if history.len() > max_revisions {
+            (&history[..max_revisions], history.get(max_revisions))
+        } else {
+            (history, None)
+        };
+
+        let mut previous = match baseline.and_then(|entry| entry["version"].as_str()) {
+            Some(version) => {
+                match Self::get_revision_content(
+                    client,
+                    &blog_file.gist_url,
+                    version,
+                    &blog_file.file,
+                ) {
+                    Ok(content) => content,
+                    Err(error) => {
+                        let message = format!(
+                            "Couldn't fetch revision `{}` of `{}`: \n\t{}",
+                            version, blog_file.file, error
+                        );
+                        push_message(Type::Warning, &message);
+                        return Vec::new();
+                    }
+                }
+            }
+            None => String::new(),
+        };
+
+        let html_url = blog_file.gist["html_url"].as_str().unwrap_or_default();
+        let mut revisions: Vec<Revision> = Vec::new();
+
+        for entry in shown.iter().rev() {
+            let version = match entry["version"].as_str() {
+                Some(version) => version,
+                None => continue,
+            };
+
+            let content = match Self::get_revision_content(
+                client,
+                &blog_file.gist_url,
+                version,
+                &blog_file.file,
+            ) {
+                Ok(content) => content,
                 Err(error) => {
                     let message = format!(
-                        "Failed while encoding response from Gist API: \n\t{}",
-                        error
+                        "Couldn't fetch revision `{}` of `{}`, later revisions are missing: \n\t{}",
+                        version, blog_file.file, error
                     );
-                    push_message(Type::Error, &message);
-                    exit(1);
+                    push_message(Type::Warning, &message);
+                    break;
                 }
-            },
+            };
+
+            // revisions which only touched other files of the gist
+            if content == previous {
+                continue;
+            }
+
+            let (diff, additions, deletions) = ParserUtils::render_diff(&previous, &content);
+
+            revisions.push(Revision {
+                version: version.to_string(),
+                committed_at: entry["committed_at"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                html_url: ParserUtils::join_url_path(html_url, version),
+                diff,
+                additions,
+                deletions,
+            });
+
+            previous = content;
+        }
+
+        revisions.reverse();
+        revisions
+    }
+
+    /// Collect the non-gisture files of a gist as attachments
+    /// (files too large for their raw URL are read from the git clone, if any)
+    fn get_attachment_files(
+        gist_files: &Map<String, Value>,
+        clone_dir: Option<&Path>,
+    ) -> Vec<AttachmentFile> {
+        gist_files
+            .iter()
+            .filter(|(file, _)| !BlogEntry::is_blog_file(file))
+            .map(|(file, metadata)| {
+                let size = metadata["size"].as_u64().unwrap_or(0);
+
+                AttachmentFile {
+                    file: file.to_string(),
+                    raw_url: metadata["raw_url"].as_str().map(String::from),
+                    local_path: clone_dir
+                        .filter(|_| size > RAW_SIZE_LIMIT)
+                        .map(|dir| dir.join(file)),
+                }
+            })
+            .collect()
+    }
+
+    /// Download the attachments of a gisture file
+    fn get_attachments(client: &GistClient, blog_file: &BlogFile) -> Vec<Attachment> {
+        let mut attachments: Vec<Attachment> = Vec::new();
+
+        for attachment_file in &blog_file.attachment_files {
+            let content = match (&attachment_file.local_path, &attachment_file.raw_url) {
+                (Some(local_path), _) => fs::read(local_path).map_err(|error| error.to_string()),
+                (None, Some(raw_url)) => client
+                    .fetch(raw_url)
+                    .map(|response| response.body)
+                    .map_err(|error| error.to_string()),
+                (None, None) => Err(String::from("it has no raw URL")),
+            };
+
+            match content {
+                Ok(content) => attachments.push(Attachment {
+                    file: attachment_file.file.clone(),
+                    content,
+                }),
+                Err(reason) => {
+                    let message = format!(
+                        "Couldn't retrieve attachment `{}` of `{}`: \n\t{} (SKIPPED)",
+                        attachment_file.file, blog_file.file, reason
+                    );
+                    push_message(Type::Warning, &message);
+                }
+            }
+        }
+
+        attachments
+    }
+
+    /// List the gisture files inside a git clone of a gist
+    fn list_cloned_blogs(clone_dir: Option<&Path>) -> Vec<String> {
+        let dir_entries = match clone_dir.map(fs::read_dir) {
+            Some(Ok(dir_entries)) => dir_entries,
+            _ => return Vec::new(),
+        };
+
+        let mut files: Vec<String> = dir_entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|file| BlogEntry::is_blog_file(file))
+            .collect();
+        files.sort();
+
+        files
+    }
+
+    /// Read a gisture file from a git clone of its gist
+    fn read_cloned_markdown(path: &Path) -> Option<String> {
+        match fs::read_to_string(path) {
+            Ok(markdown) => Some(markdown),
+            Err(error) => {
+                let message = format!("Failed to read `{}` due to: \n\t{}", path.display(), error);
+                push_message(Type::Warning, &message);
+                None
+            }
+        }
+    }
+
+    /// Fetch raw markdown for a particular gist entry
+    fn get_gist_markdown(client: &GistClient, blog_file: &BlogFile) -> Option<String> {
+        let raw_url = match (&blog_file.local_path, &blog_file.raw_url) {
+            (Some(local_path), _) => return Self::read_cloned_markdown(local_path),
+            (None, Some(raw_url)) => raw_url,
+            (None, None) => return None,
+        };
+
+        match String::from_utf8(Self::fetch_required(client, raw_url)?) {
+            Ok(markdown) => Some(markdown),
             Err(error) => {
-                let message = format!("Couldn't send HTTP request to Gist API: \n\t{}", error);
+                let message = format!(
+                    "Failed while encoding response from Gist API: \n\t{}",
+                    error
+                );
                 push_message(Type::Error, &message);
                 exit(1);
             }
         }
     }
+}
 
+impl ContentSource for GistApi {
     /// Organize a blog map of all gist entries
-    pub fn get_all_blogs() -> HashMap<String, GistPage> {
+    async fn get_all_blogs(&self) -> HashMap<String, GistPage> {
         push_message(Type::Info, "Fetching gist schema.");
 
-        // register a ureq agent (connection pool)
-        let conn_pool: Agent = AgentBuilder::new()
-            .timeout_read(Duration::from_secs(5))
-            .timeout_write(Duration::from_secs(5))
-            .build();
+        let config = Config::get_config();
 
-        let user_gists = Self::get_user_gists(&conn_pool);
+        // a ureq agent (connection pool) that retries failed requests
+        let client = GistClient::new(&config, self.offline);
 
-        // to store all blog's permalink and their contents
-        let mut blogs: HashMap<String, GistPage> = HashMap::new();
+        if self.offline {
+            push_message(Type::Info, "Building offline from the disk cache.");
+        } else if config.github_token.is_some() {
+            push_message(Type::Info, "Using authenticated access to the Gist API.");
+        }
+
+        let user_gists = Self::get_user_gists(&client);
+
+        let gist_stats = Self::get_gist_stats(&client, &config);
+        if gist_stats.is_none() && config.github_token.is_none() {
+            push_message(
+                Type::Info,
+                "Star counts need a `github_token`, counting forks through the REST API.",
+            );
+        }
+
+        // gisture files to download, in the order the Gist API listed them
+        let mut blog_files: Vec<BlogFile> = Vec::new();
+
+        // git clones of gists too large for the API (by gist ID)
+        let mut clones: HashMap<String, Option<PathBuf>> = HashMap::new();
 
         for gist in user_gists {
-            let gist_files: Map<String, Value> =
-                match serde_json::from_str(&gist["files"].to_string()) {
-                    Ok(gist_files) => gist_files,
-                    Err(error) => {
-                        let message =
-                            format!("Failed while scraping files inside Gist: \n\t{}", error);
-                        push_message(Type::Error, &message);
-                        exit(1);
-                    }
-                };
+            // gisture files of this gist are pushed after this index
+            let first_blog = blog_files.len();
 
-            for (file, metadata) in gist_files {
-                // gisture files should end with `blog.md`, this is to distinguish from other gists
-                if file.ends_with("blog.md") {
-                    // url to get the the raw markdown
-                    let raw_url = metadata["raw_url"].to_string().replace("\"", "");
-
-                    // get raw markdown of gist
-                    let markdown_content = Self::get_gist_markdown(&conn_pool, &raw_url);
-
-                    // parse blog title from markdown (`# Title`)
-                    let page_title = match markdown_content.split("\n").nth(0) {
-                        Some(title) => {
-                            if title.contains("#") {
-                                title.replace("#", "").trim().to_string()
-                            } else {
-                                let message = format!("Gist `{}` doesn't contain a title (`# Title`) on top. (SKIPPED)", file);
-                                push_message(Type::Warning, &message);
-                                continue;
-                            }
-                        }
-                        None => {
-                            let message = format!("Encountered empty Gist: `{}` (SKIPPED)", file);
+            let gist_id = gist["id"].as_str().unwrap_or_default().to_string();
+            let gist_url = Self::api_url(&config, &format!("gists/{}", gist_id));
+            let mut gist_files = Self::get_gist_files(&gist);
+
+            let (star_count, fork_count) = match gist_stats.as_ref() {
+                Some(gist_stats) => {
+                    let (star_count, fork_count) =
+                        gist_stats.get(&gist_id).copied().unwrap_or_default();
+                    (star_count, Some(fork_count))
+                }
+                None => (0, None),
+            };
+
+            // the user's gist listing truncates gists with a lot of files
+            if gist["truncated"].as_bool() == Some(true) {
+                match Self::get_gist(&client, &gist_url) {
+                    Ok(full_gist) => {
+                        gist_files = Self::get_gist_files(&full_gist);
+
+                        // past 300 files, the rest only exist in the gist's repository
+                        if full_gist["truncated"].as_bool() == Some(true) {
+                            let message =
+                                format!("Gist `{}` has more than 300 files, cloning it.", gist_id);
                             push_message(Type::Warning, &message);
-                            continue;
+
+                            let clone_dir =
+                                Self::clone_gist(&mut clones, &gist, self.offline).await;
+
+                            for file in Self::list_cloned_blogs(clone_dir.as_deref()) {
+                                if !gist_files.contains_key(&file) {
+                                    blog_files.push(BlogFile {
+                                        local_path: clone_dir.as_ref().map(|dir| dir.join(&file)),
+                                        revision: Self::pinned_revision(&config, &file),
+                                        file,
+                                        raw_url: None,
+                                        size: 0,
+                                        gist_url: gist_url.clone(),
+                                        revised_at: None,
+                                        revisions: Vec::new(),
+                                        comments: Vec::new(),
+                                        star_count,
+                                        fork_count,
+                                        attachment_files: Vec::new(),
+                                        attachments: Vec::new(),
+                                        gist: gist.clone(),
+                                    });
+                                }
+                            }
                         }
+                    }
+                    Err(error) => {
+                        let message = format!(
+                            "Couldn't fetch truncated gist `{}`, some files may be missing: \n\t{}",
+                            gist_id, error
+                        );
+                        push_message(Type::Warning, &message);
+                    }
+                }
+            }
+
+            for (file, metadata) in &gist_files {
+                // gisture files should end with `blog.md` (or `blog.ipynb`...), this is to distinguish from other gists
+                if BlogEntry::is_blog_file(file) {
+                    let size = metadata["size"].as_u64().unwrap_or(0);
+
+                    let local_path = if size > RAW_SIZE_LIMIT {
+                        Self::clone_gist(&mut clones, &gist, self.offline)
+                            .await
+                            .map(|dir| dir.join(file))
+                    } else {
+                        None
                     };
 
-                    // permalink is the filename without the gisture markdown extension
-                    let permalink = file.replace(".blog.md", "");
+                    blog_files.push(BlogFile {
+                        revision: Self::pinned_revision(&config, file),
+                        file: file.clone(),
+                        // url to get the the raw markdown
+                        raw_url: metadata["raw_url"].as_str().map(String::from),
+                        size,
+                        local_path,
+                        gist_url: gist_url.clone(),
+                        revised_at: None,
+                        revisions: Vec::new(),
+                        comments: Vec::new(),
+                        star_count,
+                        fork_count,
+                        attachment_files: Vec::new(),
+                        attachments: Vec::new(),
+                        gist: gist.clone(),
+                    });
+                }
+            }
 
-                    // convert gist Markdown to HTML
-                    let html_content = ParserUtils::parse_markdown_to_html(markdown_content);
+            // every gisture file in the gist gets the other files as attachments
+            if blog_files.len() > first_blog {
+                let too_large = gist_files.iter().any(|(file, metadata)| {
+                    !BlogEntry::is_blog_file(file)
+                        && metadata["size"].as_u64().unwrap_or(0) > RAW_SIZE_LIMIT
+                });
 
-                    let page_data: GistPage = GistPage {
-                        title: page_title,
-                        description: gist["description"].to_string().replace("\"", ""),
-                        created_at: gist["created_at"].to_string().replace("\"", ""),
-                        updated_at: gist["updated_at"].to_string().replace("\"", ""),
-                        html_url: gist["html_url"].to_string().replace("\"", ""),
-                        content: html_content,
-                    };
+                let clone_dir = if too_large {
+                    Self::clone_gist(&mut clones, &gist, self.offline).await
+                } else {
+                    None
+                };
 
-                    // save blog with it's raw markdown
-                    blogs.insert(permalink, page_data);
+                let attachment_files =
+                    Self::get_attachment_files(&gist_files, clone_dir.as_deref());
 
-                    let message = format!("Fetched blog \"{}\".", file);
-                    push_message(Type::Info, &message);
+                for blog_file in &mut blog_files[first_blog..] {
+                    blog_file.attachment_files = attachment_files.clone();
                 }
             }
         }
 
+        let concurrency = config.max_concurrent_downloads.max(1);
+
+        let show_comments = config.show_comments;
+
+        // every revision shown costs a couple of requests, so it's opt-in
+        let max_revisions = if config.show_revisions {
+            config.max_revisions
+        } else {
+            0
+        };
+
+        let message = format!(
+            "Downloading {} gisture blog(s) ({} at a time).",
+            blog_files.len(),
+            concurrency
+        );
+        push_message(Type::Info, &message);
+
+        // get raw markdown of gists in parallel, `buffered` keeps the original order
+        let downloads: Vec<(BlogFile, Option<String>)> = stream::iter(blog_files)
+            .map(|mut blog_file| {
+                let client = client.clone();
+                let options = self.options;
+                task::spawn_blocking(move || {
+                    let mut markdown_content = Self::get_gist_markdown(&client, &blog_file);
+
+                    // pinned in `gisture.json` or with a marker in the latest revision
+                    let revision = blog_file.revision.clone().or_else(|| {
+                        markdown_content
+                            .as_deref()
+                            .and_then(ParserUtils::parse_revision_marker)
+                    });
+
+                    // an unapproved latest revision should never go live, so no fallback
+                    if let Some(revision) = revision {
+                        markdown_content =
+                            Self::get_revision_markdown(&client, &mut blog_file, &revision);
+                    }
+
+                    if markdown_content.is_some() && max_revisions > 0 {
+                        blog_file.revisions =
+                            Self::get_revision_history(&client, &blog_file, max_revisions);
+                    }
+
+                    // no need to ask for comments when the listing says there are none
+                    let has_comments = blog_file.gist["comments"].as_u64() != Some(0);
+                    if markdown_content.is_some() && show_comments && has_comments {
+                        blog_file.comments = Self::get_gist_comments(&client, &blog_file, &options);
+                    }
+
+                    if markdown_content.is_some() {
+                        blog_file.attachments = Self::get_attachments(&client, &blog_file);
+                    }
+
+                    if markdown_content.is_some() && blog_file.fork_count.is_none() {
+                        blog_file.fork_count = Some(Self::get_fork_count(&client, &blog_file));
+                    }
+
+                    (blog_file, markdown_content)
+                })
+            })
+            .buffered(concurrency)
+            .map(|download| match download {
+                Ok(download) => download,
+                Err(error) => {
+                    let message = format!("Failed while downloading gists: \n\t{}", error);
+                    push_message(Type::Error, &message);
+                    exit(1);
+                }
+            })
+            .collect()
+            .await;
+
+        // to store all blog's permalink and their contents
+        let mut blogs: HashMap<String, GistPage> = HashMap::new();
+
+        for (blog_file, markdown_content) in downloads {
+            let mut markdown_content = match markdown_content {
+                Some(markdown_content) => markdown_content,
+                None => {
+                    let message = format!("Couldn't retrieve gist `{}`. (SKIPPED)", blog_file.file);
+                    push_message(Type::Warning, &message);
+                    continue;
+                }
+            };
+
+            // a partial download means the file has to come from the gist's repository
+            // (which only has the latest revision checked out)
+            if blog_file.local_path.is_none()
+                && blog_file.revision.is_none()
+                && (markdown_content.len() as u64) < blog_file.size
+            {
+                let full_content = Self::clone_gist(&mut clones, &blog_file.gist, self.offline)
+                    .await
+                    .and_then(|dir| Self::read_cloned_markdown(&dir.join(&blog_file.file)));
+
+                match full_content {
+                    Some(full_content) => markdown_content = full_content,
+                    None => {
+                        let message = format!(
+                            "Gist `{}` is truncated ({} of {} bytes), its content couldn't be fully retrieved.",
+                            blog_file.file,
+                            markdown_content.len(),
+                            blog_file.size
+                        );
+                        push_message(Type::Warning, &message);
+                    }
+                }
+            }
+
+            let BlogFile {
+                file,
+                gist,
+                revision,
+                revised_at,
+                revisions,
+                comments,
+                star_count,
+                fork_count,
+                attachments,
+                ..
+            } = blog_file;
+
+            // a pinned page was last updated when its revision was committed
+            let updated_at =
+                revised_at.unwrap_or_else(|| gist["updated_at"].to_string().replace('"', ""));
+
+            let blog_entry = BlogEntry {
+                file: file.clone(),
+                markdown: markdown_content,
+                description: gist["description"].to_string().replace('"', ""),
+                created_at: gist["created_at"].to_string().replace('"', ""),
+                updated_at,
+                html_url: gist["html_url"].to_string().replace('"', ""),
+                revision,
+                revisions,
+                comments,
+                star_count,
+                fork_count: fork_count.unwrap_or(0),
+                attachments,
+            };
+
+            let (permalink, page_data) = match blog_entry.into_page(&self.options) {
+                Some(page) => page,
+                None => continue,
+            };
+
+            // save blog with it's raw markdown (a slug may take another blog's permalink)
+            match blogs.entry(permalink) {
+                Entry::Occupied(entry) => {
+                    let message = format!(
+                        "`{}` has the same permalink as another blog (`{}`). (SKIPPED)",
+                        file,
+                        entry.key()
+                    );
+                    push_message(Type::Warning, &message);
+                    continue;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(page_data);
+                }
+            }
+
+            let message = format!("Fetched blog \"{}\".", file);
+            push_message(Type::Info, &message);
+        }
+
         // if there are no gisture blogs, why should I live any longer?
-        if blogs.len() == 0 {
-            let username = Config::get_config().github_username;
-            let message = format!("0 gisture blogs (*.blog.md) found for user '{}'", username);
+        if blogs.is_empty() {
+            let message = format!(
+                "0 gisture blogs ({}) found for user '{}'",
+                BlogEntry::blog_patterns(),
+                config.github_username
+            );
             push_message(Type::Warning, &message);
             exit(0)
         }
@@ -1,13 +1,19 @@
 mod cache;
+mod client;
 mod cli;
 mod config;
 mod engine;
 mod gist;
+mod math;
 mod messages;
 mod metadata;
+mod notebook;
 mod parsers;
 mod serve;
+mod source;
+mod table;
 mod template;
+mod toc;
 
 #[tokio::main]
 async fn main() {
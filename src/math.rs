@@ -0,0 +1,980 @@
+use std::mem;
+
+/// LaTeX math (`$...$` and `$$...$$`) converted to MathML
+pub(crate) struct Math {
+    chars: Vec<char>,
+    position: usize,
+    // block math puts the limits of `\sum` and such below and above it
+    display: bool,
+    // font of the letters and numbers, set by `\mathbf{...}` and such
+    variant: Option<&'static str>,
+}
+
+/// A piece of MathML, and whether scripts on it are limits (`\sum_{i=0}^n`)
+struct Node {
+    mathml: String,
+    limits: bool,
+}
+
+impl Node {
+    fn new(mathml: String) -> Self {
+        Self {
+            mathml,
+            limits: false,
+        }
+    }
+}
+
+impl Math {
+    /// Convert a LaTeX expression to MathML (`display` for math in a block of its own)
+    pub fn to_mathml(latex: &str, display: bool) -> Result<String, String> {
+        let mathml = Self::parse(latex, display)?;
+
+        if display {
+            Ok(format!("<math display=\"block\">{}</math>", mathml))
+        } else {
+            Ok(format!("<math>{}</math>", mathml))
+        }
+    }
+
+    /// MathML of a whole expression
+    fn parse(latex: &str, display: bool) -> Result<String, String> {
+        let mut math = Self {
+            chars: latex.chars().collect(),
+            position: 0,
+            display,
+            variant: None,
+        };
+
+        let row = math.parse_row()?;
+
+        match math.peek() {
+            None => Ok(Self::mrow(row)),
+            Some('\\') => Err(format!("unexpected `\\{}`", math.read_command())),
+            Some(c) => Err(format!("unexpected `{}`", c)),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// Consume a command, a name of letters (`\alpha`) or a single other character (`\,`)
+    fn read_command(&mut self) -> String {
+        // the backslash
+        self.position += 1;
+
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.position += 1;
+        }
+
+        if self.position == start && self.peek().is_some() {
+            self.position += 1;
+        }
+
+        self.chars[start..self.position].iter().collect()
+    }
+
+    /// Name of the command at the current position, without consuming it
+    fn peek_command(&mut self) -> Option<String> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+
+        let position = self.position;
+        let command = self.read_command();
+        self.position = position;
+
+        Some(command)
+    }
+
+    /// Consume a character that has to come next
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(format!("missing `{}`", expected))
+        }
+    }
+
+    /// Raw text of a `{...}` argument (`\text{...}`, `\begin{...}`)
+    fn read_braced(&mut self, command: &str) -> Result<String, String> {
+        self.skip_whitespace();
+
+        if self.peek() != Some('{') {
+            return Err(format!("`\\{}` is missing its `{{...}}` argument", command));
+        }
+        self.position += 1;
+
+        let start = self.position;
+        let mut depth = 0;
+
+        while let Some(c) = self.peek() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => {
+                    let text = self.chars[start..self.position].iter().collect();
+                    self.position += 1;
+                    return Ok(text);
+                }
+                '}' => depth -= 1,
+                _ => (),
+            }
+            self.position += 1;
+        }
+
+        Err(String::from("missing `}`"))
+    }
+
+    /// Nodes up to the end of a group, a cell or the expression
+    fn parse_row(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes: Vec<Node> = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+
+            match self.peek() {
+                None | Some('}') | Some('&') => break,
+                Some('\\')
+                    if matches!(
+                        self.peek_command().as_deref(),
+                        Some("\\") | Some("right") | Some("end")
+                    ) =>
+                {
+                    break
+                }
+                Some('^') | Some('_') => {
+                    let base = nodes
+                        .pop()
+                        .unwrap_or_else(|| Node::new(String::from("<mrow></mrow>")));
+                    nodes.push(self.parse_scripts(base)?);
+                }
+                Some(_) => {
+                    // `\displaystyle` and such don't render anything
+                    let node = self.parse_atom(false)?;
+                    if !node.mathml.is_empty() {
+                        nodes.push(node);
+                    }
+                }
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    /// Subscript and superscript of a node (`x_i^2`)
+    fn parse_scripts(&mut self, base: Node) -> Result<Node, String> {
+        let mut subscript = None;
+        let mut superscript = None;
+
+        loop {
+            self.skip_whitespace();
+
+            let subscript_next = match self.peek() {
+                Some('_') => true,
+                Some('^') => false,
+                _ => break,
+            };
+            self.position += 1;
+
+            let (script, name) = if subscript_next {
+                (&mut subscript, "_")
+            } else {
+                (&mut superscript, "^")
+            };
+
+            if script.is_some() {
+                return Err(format!("double `{}`", name));
+            }
+            *script = Some(self.parse_argument(name)?);
+        }
+
+        let (under, over, under_over) = if base.limits && self.display {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+
+        let mathml = match (subscript, superscript) {
+            (Some(subscript), Some(superscript)) => format!(
+                "<{0}>{1}{2}{3}</{0}>",
+                under_over, base.mathml, subscript, superscript
+            ),
+            (Some(subscript), None) => format!("<{0}>{1}{2}</{0}>", under, base.mathml, subscript),
+            (None, Some(superscript)) => {
+                format!("<{0}>{1}{2}</{0}>", over, base.mathml, superscript)
+            }
+            (None, None) => return Ok(base),
+        };
+
+        Ok(Node::new(mathml))
+    }
+
+    /// The single atom or group a command or script applies to
+    fn parse_argument(&mut self, command: &str) -> Result<String, String> {
+        self.skip_whitespace();
+
+        let missing = match self.peek() {
+            None | Some('}') | Some('&') | Some('^') | Some('_') => true,
+            Some('\\') => matches!(
+                self.peek_command().as_deref(),
+                Some("\\") | Some("right") | Some("end")
+            ),
+            _ => false,
+        };
+
+        if missing {
+            return Err(format!("`{}` is missing an argument", command));
+        }
+
+        Ok(self.parse_atom(true)?.mathml)
+    }
+
+    /// A group, command, number, letter or operator (a `single` digit for arguments, `x^23`
+    /// is `x` squared then 3)
+    fn parse_atom(&mut self, single: bool) -> Result<Node, String> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err(String::from("unexpected end of the expression")),
+        };
+
+        if c == '\\' {
+            let command = self.read_command();
+            return self.parse_command(&command);
+        }
+
+        self.position += 1;
+
+        let node = match c {
+            '{' => {
+                let row = self.parse_row()?;
+                self.expect('}')?;
+                Node::new(Self::mrow(row))
+            }
+            '}' => return Err(String::from("unexpected `}`")),
+            c if c.is_ascii_digit() => {
+                let mut number = c.to_string();
+                if !single {
+                    loop {
+                        match (self.peek(), self.chars.get(self.position + 1)) {
+                            (Some(c), _) if c.is_ascii_digit() => number.push(c),
+                            (Some('.'), Some(next)) if next.is_ascii_digit() => number.push('.'),
+                            _ => break,
+                        }
+                        self.position += 1;
+                    }
+                }
+                Node::new(self.token("mn", &number))
+            }
+            c if c.is_alphabetic() => Node::new(self.token("mi", &c.to_string())),
+            '\'' => Node::new(Self::element("mo", "′")),
+            '-' => Node::new(Self::element("mo", "−")),
+            '*' => Node::new(Self::element("mo", "∗")),
+            '~' => Node::new(Self::space("0.333em")),
+            c => Node::new(Self::element("mo", &c.to_string())),
+        };
+
+        Ok(node)
+    }
+
+    /// A command (without its backslash) and its arguments
+    fn parse_command(&mut self, command: &str) -> Result<Node, String> {
+        if let Some((letter, upright)) = Self::greek_letter(command) {
+            let mathml = if upright {
+                format!("<mi mathvariant=\"normal\">{}</mi>", letter)
+            } else {
+                Self::element("mi", letter)
+            };
+            return Ok(Node::new(mathml));
+        }
+
+        if let Some(symbol) = Self::identifier(command) {
+            return Ok(Node::new(Self::element("mi", symbol)));
+        }
+
+        if let Some(symbol) = Self::operator(command) {
+            return Ok(Node::new(Self::element("mo", symbol)));
+        }
+
+        if let Some((symbol, limits)) = Self::big_operator(command) {
+            return Ok(Node {
+                mathml: Self::element("mo", symbol),
+                limits,
+            });
+        }
+
+        if let Some(limits) = Self::function(command) {
+            return Ok(Node {
+                mathml: Self::element("mi", command),
+                limits,
+            });
+        }
+
+        if let Some(accent) = Self::accent(command) {
+            let argument = self.parse_argument(&format!("\\{}", command))?;
+            let mathml = match command {
+                "underline" => format!(
+                    "<munder accentunder=\"true\">{}{}</munder>",
+                    argument,
+                    Self::element("mo", accent)
+                ),
+                "underbrace" => format!(
+                    "<munder>{}{}</munder>",
+                    argument,
+                    Self::element("mo", accent)
+                ),
+                "overbrace" => {
+                    format!("<mover>{}{}</mover>", argument, Self::element("mo", accent))
+                }
+                _ => format!(
+                    "<mover accent=\"true\">{}{}</mover>",
+                    argument,
+                    Self::element("mo", accent)
+                ),
+            };
+            // the text of a brace goes below or above it
+            return Ok(Node {
+                mathml,
+                limits: command.ends_with("brace"),
+            });
+        }
+
+        if let Some(variant) = Self::font(command) {
+            let previous = self.variant.replace(variant);
+            let argument = self.parse_argument(&format!("\\{}", command));
+            self.variant = previous;
+            return Ok(Node::new(argument?));
+        }
+
+        let mathml = match command {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument(&format!("\\{}", command))?;
+                let denominator = self.parse_argument(&format!("\\{}", command))?;
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let n = self.parse_argument("\\binom")?;
+                let k = self.parse_argument("\\binom")?;
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    n, k
+                )
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+
+                // `\sqrt[3]{x}` is a cube root
+                let index = if self.peek() == Some('[') {
+                    self.position += 1;
+                    let start = self.position;
+                    while self.peek().is_some_and(|c| c != ']') {
+                        self.position += 1;
+                    }
+                    let index: String = self.chars[start..self.position].iter().collect();
+                    self.expect(']')?;
+                    Some(Self::parse(&index, self.display)?)
+                } else {
+                    None
+                };
+
+                let radicand = self.parse_argument("\\sqrt")?;
+                match index {
+                    Some(index) => format!("<mroot>{}{}</mroot>", radicand, index),
+                    None => format!("<msqrt>{}</msqrt>", radicand),
+                }
+            }
+            "text" | "textrm" | "textit" | "textbf" | "textsf" | "texttt" | "mbox" => {
+                let text = self.read_braced(command)?;
+                Self::element("mtext", &text)
+            }
+            "operatorname" => {
+                let name = self.read_braced(command)?;
+                format!("<mi mathvariant=\"normal\">{}</mi>", Self::escape(&name))
+            }
+            "left" => return self.parse_fenced(),
+            "middle" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr"
+            | "biggl" | "biggr" | "Biggl" | "Biggr" => {
+                let delimiter = self.read_delimiter(command)?;
+                Self::element("mo", delimiter)
+            }
+            "begin" => self.parse_environment()?,
+            "," | "thinspace" => Self::space("0.167em"),
+            ":" | ">" | "medspace" => Self::space("0.222em"),
+            ";" | "thickspace" => Self::space("0.278em"),
+            " " => Self::space("0.333em"),
+            "quad" => Self::space("1em"),
+            "qquad" => Self::space("2em"),
+            "!" => Self::space("-0.167em"),
+            // sizes and styles are left to the browser
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" => String::new(),
+            "" => return Err(String::from("`\\` at the end of the expression")),
+            _ => return Err(format!("unknown command `\\{}`", command)),
+        };
+
+        Ok(Node::new(mathml))
+    }
+
+    /// `\left( ... \right)`, the delimiters stretch to fit what they enclose
+    fn parse_fenced(&mut self) -> Result<Node, String> {
+        let open = self.read_delimiter("left")?;
+        let row = self.parse_row()?;
+
+        if self.peek_command().as_deref() != Some("right") {
+            return Err(String::from("`\\left` is missing its `\\right`"));
+        }
+        self.read_command();
+        let close = self.read_delimiter("right")?;
+
+        let mut mathml = String::from("<mrow>");
+        if !open.is_empty() {
+            mathml.push_str(&format!("<mo fence=\"true\">{}</mo>", Self::escape(open)));
+        }
+        for node in row {
+            mathml.push_str(&node.mathml);
+        }
+        if !close.is_empty() {
+            mathml.push_str(&format!("<mo fence=\"true\">{}</mo>", Self::escape(close)));
+        }
+        mathml.push_str("</mrow>");
+
+        Ok(Node::new(mathml))
+    }
+
+    /// Delimiter after `\left`, `\right` or `\big` (`.` is none)
+    fn read_delimiter(&mut self, command: &str) -> Result<&'static str, String> {
+        self.skip_whitespace();
+
+        let delimiter = match self.peek() {
+            Some('\\') => {
+                let name = self.read_command();
+                match name.as_str() {
+                    "{" | "lbrace" => Some("{"),
+                    "}" | "rbrace" => Some("}"),
+                    "|" | "Vert" | "lVert" | "rVert" => Some("‖"),
+                    "vert" | "lvert" | "rvert" => Some("|"),
+                    "langle" => Some("⟨"),
+                    "rangle" => Some("⟩"),
+                    "lfloor" => Some("⌊"),
+                    "rfloor" => Some("⌋"),
+                    "lceil" => Some("⌈"),
+                    "rceil" => Some("⌉"),
+                    "backslash" => Some("\\"),
+                    _ => None,
+                }
+            }
+            Some(c) => {
+                self.position += 1;
+                match c {
+                    '.' => Some(""),
+                    '(' => Some("("),
+                    ')' => Some(")"),
+                    '[' => Some("["),
+                    ']' => Some("]"),
+                    '|' => Some("|"),
+                    '/' => Some("/"),
+                    '<' => Some("⟨"),
+                    '>' => Some("⟩"),
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+
+        delimiter.ok_or_else(|| format!("`\\{}` is missing its delimiter", command))
+    }
+
+    /// `\begin{name} ... \end{name}`, rows end with `\\` and cells with `&`
+    fn parse_environment(&mut self) -> Result<String, String> {
+        let name = self.read_braced("begin")?;
+
+        let (open, close, mut align) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", String::new()),
+            "pmatrix" => ("(", ")", String::new()),
+            "bmatrix" => ("[", "]", String::new()),
+            "Bmatrix" => ("{", "}", String::new()),
+            "vmatrix" => ("|", "|", String::new()),
+            "Vmatrix" => ("‖", "‖", String::new()),
+            "cases" => ("{", "", String::from("left left")),
+            "aligned" | "align" | "align*" | "split" => {
+                ("", "", String::from("right left right left right left"))
+            }
+            "gathered" | "gather" | "gather*" => ("", "", String::new()),
+            "array" => ("", "", String::new()),
+            _ => return Err(format!("unknown environment `{}`", name)),
+        };
+
+        // columns of an array are given as `{lcr}`
+        if name == "array" {
+            let columns: Vec<&str> = self
+                .read_braced("begin{array}")?
+                .chars()
+                .filter_map(|c| match c {
+                    'l' => Some("left"),
+                    'c' => Some("center"),
+                    'r' => Some("right"),
+                    _ => None,
+                })
+                .collect();
+            align = columns.join(" ");
+        }
+
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        let mut cells: Vec<String> = Vec::new();
+
+        loop {
+            let cell = self.parse_row()?;
+            cells.push(Self::mrow(cell));
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some('&') => self.position += 1,
+                Some('\\') => match self.read_command().as_str() {
+                    "\\" => {
+                        rows.push(mem::take(&mut cells));
+
+                        // `\\[4pt]` adds space between the rows
+                        self.skip_whitespace();
+                        if self.peek() == Some('[') {
+                            while self.peek().is_some_and(|c| c != ']') {
+                                self.position += 1;
+                            }
+                            self.expect(']')?;
+                        }
+                    }
+                    "end" => {
+                        let end = self.read_braced("end")?;
+                        if end != name {
+                            return Err(format!(
+                                "`\\begin{{{}}}` is closed by `\\end{{{}}}`",
+                                name, end
+                            ));
+                        }
+                        rows.push(cells);
+                        break;
+                    }
+                    command => return Err(format!("unexpected `\\{}`", command)),
+                },
+                Some(c) => return Err(format!("unexpected `{}`", c)),
+                None => {
+                    return Err(format!(
+                        "`\\begin{{{0}}}` is missing its `\\end{{{0}}}`",
+                        name
+                    ))
+                }
+            }
+        }
+
+        // a `\\` after the last row
+        if rows.len() > 1 && rows.last().is_some_and(|row| row == &["<mrow></mrow>"]) {
+            rows.pop();
+        }
+
+        let mut mathml = String::new();
+        if !open.is_empty() || !close.is_empty() {
+            mathml.push_str("<mrow>");
+        }
+        if !open.is_empty() {
+            mathml.push_str(&Self::element("mo", open));
+        }
+
+        if align.is_empty() {
+            mathml.push_str("<mtable>");
+        } else {
+            mathml.push_str(&format!("<mtable columnalign=\"{}\">", align));
+        }
+        for row in rows {
+            mathml.push_str("<mtr>");
+            for cell in row {
+                mathml.push_str(&format!("<mtd>{}</mtd>", cell));
+            }
+            mathml.push_str("</mtr>");
+        }
+        mathml.push_str("</mtable>");
+
+        if !close.is_empty() {
+            mathml.push_str(&Self::element("mo", close));
+        }
+        if !open.is_empty() || !close.is_empty() {
+            mathml.push_str("</mrow>");
+        }
+
+        Ok(mathml)
+    }
+
+    /// Nodes as a single element
+    fn mrow(nodes: Vec<Node>) -> String {
+        match nodes.len() {
+            1 => nodes.into_iter().map(|node| node.mathml).collect(),
+            _ => {
+                let mathml: String = nodes.into_iter().map(|node| node.mathml).collect();
+                format!("<mrow>{}</mrow>", mathml)
+            }
+        }
+    }
+
+    /// A letter or number in the current font
+    fn token(&self, tag: &str, text: &str) -> String {
+        match self.variant {
+            Some(variant) => format!(
+                "<{0} mathvariant=\"{1}\">{2}</{0}>",
+                tag,
+                variant,
+                Self::escape(text)
+            ),
+            None => Self::element(tag, text),
+        }
+    }
+
+    fn element(tag: &str, text: &str) -> String {
+        format!("<{0}>{1}</{0}>", tag, Self::escape(text))
+    }
+
+    fn space(width: &str) -> String {
+        format!("<mspace width=\"{}\"/>", width)
+    }
+
+    /// The MathML is inlined in markdown, so the punctuation becomes character references that
+    /// neither markdown nor HTML pick up
+    fn escape(text: &str) -> String {
+        text.chars()
+            .map(|c| {
+                if c.is_ascii_punctuation() {
+                    format!("&#x{:X};", c as u32)
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect()
+    }
+
+    /// Greek letters, and whether they're upright (capitals)
+    fn greek_letter(command: &str) -> Option<(&'static str, bool)> {
+        let letter = match command {
+            "alpha" => "α",
+            "beta" => "β",
+            "gamma" => "γ",
+            "delta" => "δ",
+            "epsilon" => "ϵ",
+            "varepsilon" => "ε",
+            "zeta" => "ζ",
+            "eta" => "η",
+            "theta" => "θ",
+            "vartheta" => "ϑ",
+            "iota" => "ι",
+            "kappa" => "κ",
+            "lambda" => "λ",
+            "mu" => "μ",
+            "nu" => "ν",
+            "xi" => "ξ",
+            "omicron" => "ο",
+            "pi" => "π",
+            "varpi" => "ϖ",
+            "rho" => "ρ",
+            "varrho" => "ϱ",
+            "sigma" => "σ",
+            "varsigma" => "ς",
+            "tau" => "τ",
+            "upsilon" => "υ",
+            "phi" => "ϕ",
+            "varphi" => "φ",
+            "chi" => "χ",
+            "psi" => "ψ",
+            "omega" => "ω",
+            "Gamma" => return Some(("Γ", true)),
+            "Delta" => return Some(("Δ", true)),
+            "Theta" => return Some(("Θ", true)),
+            "Lambda" => return Some(("Λ", true)),
+            "Xi" => return Some(("Ξ", true)),
+            "Pi" => return Some(("Π", true)),
+            "Sigma" => return Some(("Σ", true)),
+            "Upsilon" => return Some(("Υ", true)),
+            "Phi" => return Some(("Φ", true)),
+            "Psi" => return Some(("Ψ", true)),
+            "Omega" => return Some(("Ω", true)),
+            _ => return None,
+        };
+
+        Some((letter, false))
+    }
+
+    /// Symbols that stand for a value
+    fn identifier(command: &str) -> Option<&'static str> {
+        let symbol = match command {
+            "infty" => "∞",
+            "partial" => "∂",
+            "nabla" => "∇",
+            "emptyset" | "varnothing" => "∅",
+            "hbar" => "ℏ",
+            "ell" => "ℓ",
+            "aleph" => "ℵ",
+            "Re" => "ℜ",
+            "Im" => "ℑ",
+            "wp" => "℘",
+            "imath" => "ı",
+            "jmath" => "ȷ",
+            _ => return None,
+        };
+
+        Some(symbol)
+    }
+
+    /// Operators, relations, arrows and punctuation
+    fn operator(command: &str) -> Option<&'static str> {
+        let symbol = match command {
+            "cdot" => "⋅",
+            "times" => "×",
+            "div" => "÷",
+            "pm" => "±",
+            "mp" => "∓",
+            "ast" => "∗",
+            "star" => "⋆",
+            "circ" => "∘",
+            "bullet" => "∙",
+            "oplus" => "⊕",
+            "ominus" => "⊖",
+            "otimes" => "⊗",
+            "odot" => "⊙",
+            "wedge" | "land" => "∧",
+            "vee" | "lor" => "∨",
+            "neg" | "lnot" => "¬",
+            "cap" => "∩",
+            "cup" => "∪",
+            "setminus" => "∖",
+            "leq" | "le" => "≤",
+            "geq" | "ge" => "≥",
+            "neq" | "ne" => "≠",
+            "ll" => "≪",
+            "gg" => "≫",
+            "approx" => "≈",
+            "equiv" => "≡",
+            "sim" => "∼",
+            "simeq" => "≃",
+            "cong" => "≅",
+            "propto" => "∝",
+            "in" => "∈",
+            "notin" => "∉",
+            "ni" => "∋",
+            "subset" => "⊂",
+            "subseteq" => "⊆",
+            "supset" => "⊃",
+            "supseteq" => "⊇",
+            "forall" => "∀",
+            "exists" => "∃",
+            "nexists" => "∄",
+            "to" | "rightarrow" => "→",
+            "gets" | "leftarrow" => "←",
+            "leftrightarrow" => "↔",
+            "Rightarrow" => "⇒",
+            "Leftarrow" => "⇐",
+            "Leftrightarrow" => "⇔",
+            "implies" | "Longrightarrow" => "⟹",
+            "iff" | "Longleftrightarrow" => "⟺",
+            "longrightarrow" => "⟶",
+            "longleftarrow" => "⟵",
+            "mapsto" => "↦",
+            "uparrow" => "↑",
+            "downarrow" => "↓",
+            "ldots" | "dots" => "…",
+            "cdots" => "⋯",
+            "vdots" => "⋮",
+            "ddots" => "⋱",
+            "mid" => "∣",
+            "parallel" => "∥",
+            "perp" | "bot" => "⊥",
+            "top" => "⊤",
+            "angle" => "∠",
+            "triangle" => "△",
+            "prime" => "′",
+            "vdash" => "⊢",
+            "models" => "⊨",
+            "because" => "∵",
+            "therefore" => "∴",
+            "colon" => ":",
+            "langle" => "⟨",
+            "rangle" => "⟩",
+            "lfloor" => "⌊",
+            "rfloor" => "⌋",
+            "lceil" => "⌈",
+            "rceil" => "⌉",
+            "vert" => "|",
+            "Vert" | "|" => "‖",
+            "{" | "lbrace" => "{",
+            "}" | "rbrace" => "}",
+            "%" => "%",
+            "$" => "$",
+            "&" => "&",
+            "#" => "#",
+            "_" => "_",
+            _ => return None,
+        };
+
+        Some(symbol)
+    }
+
+    /// Sums, products and integrals, and whether their scripts are limits in display math
+    fn big_operator(command: &str) -> Option<(&'static str, bool)> {
+        let operator = match command {
+            "sum" => ("∑", true),
+            "prod" => ("∏", true),
+            "coprod" => ("∐", true),
+            "bigcup" => ("⋃", true),
+            "bigcap" => ("⋂", true),
+            "bigoplus" => ("⨁", true),
+            "bigotimes" => ("⨂", true),
+            "bigvee" => ("⋁", true),
+            "bigwedge" => ("⋀", true),
+            "int" => ("∫", false),
+            "iint" => ("∬", false),
+            "iiint" => ("∭", false),
+            "oint" => ("∮", false),
+            _ => return None,
+        };
+
+        Some(operator)
+    }
+
+    /// Named functions (`\sin`), and whether their scripts are limits in display math (`\lim`)
+    fn function(command: &str) -> Option<bool> {
+        match command {
+            "arccos" | "arcsin" | "arctan" | "arg" | "cos" | "cosh" | "cot" | "coth" | "csc"
+            | "deg" | "dim" | "exp" | "hom" | "ker" | "lg" | "ln" | "log" | "sec" | "sin"
+            | "sinh" | "tan" | "tanh" => Some(false),
+            "det" | "gcd" | "inf" | "lim" | "liminf" | "limsup" | "max" | "min" | "Pr" | "sup" => {
+                Some(true)
+            }
+            _ => None,
+        }
+    }
+
+    /// Marks above or below their argument
+    fn accent(command: &str) -> Option<&'static str> {
+        let accent = match command {
+            "hat" | "widehat" => "^",
+            "bar" | "overline" => "¯",
+            "underline" => "_",
+            "vec" | "overrightarrow" => "→",
+            "overleftarrow" => "←",
+            "dot" => "˙",
+            "ddot" => "¨",
+            "tilde" | "widetilde" => "~",
+            "check" => "ˇ",
+            "breve" => "˘",
+            "overbrace" => "⏞",
+            "underbrace" => "⏟",
+            _ => return None,
+        };
+
+        Some(accent)
+    }
+
+    /// Fonts of the letters and numbers in their argument
+    fn font(command: &str) -> Option<&'static str> {
+        let variant = match command {
+            "mathrm" => "normal",
+            "mathit" => "italic",
+            "mathbf" | "boldsymbol" => "bold",
+            "mathbb" => "double-struck",
+            "mathcal" => "script",
+            "mathfrak" => "fraktur",
+            "mathsf" => "sans-serif",
+            "mathtt" => "monospace",
+            _ => return None,
+        };
+
+        Some(variant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_inline_math() {
+        assert_eq!(
+            Math::to_mathml("E = mc^2", false).unwrap(),
+            "<math><mrow><mi>E</mi><mo>&#x3D;</mo><mi>m</mi>\
+             <msup><mi>c</mi><mn>2</mn></msup></mrow></math>"
+        );
+    }
+
+    #[test]
+    fn renders_display_math() {
+        assert_eq!(
+            Math::to_mathml(r"\frac{a}{b}", true).unwrap(),
+            "<math display=\"block\"><mfrac><mi>a</mi><mi>b</mi></mfrac></math>"
+        );
+    }
+
+    #[test]
+    fn renders_scripts_and_limits() {
+        assert_eq!(
+            Math::to_mathml("x_i^2", false).unwrap(),
+            "<math><msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup></math>"
+        );
+        assert_eq!(
+            Math::to_mathml(r"\sum_{i=1}^n i", true).unwrap(),
+            "<math display=\"block\"><mrow><munderover><mo>∑</mo>\
+             <mrow><mi>i</mi><mo>&#x3D;</mo><mn>1</mn></mrow><mi>n</mi></munderover>\
+             <mi>i</mi></mrow></math>"
+        );
+    }
+
+    #[test]
+    fn renders_roots_and_symbols() {
+        assert_eq!(
+            Math::to_mathml(r"\sqrt[3]{x}", false).unwrap(),
+            "<math><mroot><mi>x</mi><mn>3</mn></mroot></math>"
+        );
+        assert_eq!(
+            Math::to_mathml(r"\alpha < 3.14", false).unwrap(),
+            "<math><mrow><mi>α</mi><mo>&#x3C;</mo><mn>3&#x2E;14</mn></mrow></math>"
+        );
+    }
+
+    #[test]
+    fn renders_matrices_and_fences() {
+        assert_eq!(
+            Math::to_mathml(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}", false).unwrap(),
+            "<math><mrow><mo>&#x28;</mo><mtable>\
+             <mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>\
+             <mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr>\
+             </mtable><mo>&#x29;</mo></mrow></math>"
+        );
+        assert_eq!(
+            Math::to_mathml(r"\left( x \right)", false).unwrap(),
+            "<math><mrow><mo fence=\"true\">&#x28;</mo><mi>x</mi>\
+             <mo fence=\"true\">&#x29;</mo></mrow></math>"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_latex() {
+        assert_eq!(
+            Math::to_mathml(r"\frac{a}", false).unwrap_err(),
+            r"`\frac` is missing an argument"
+        );
+        assert_eq!(
+            Math::to_mathml(r"\foo", false).unwrap_err(),
+            r"unknown command `\foo`"
+        );
+        assert_eq!(
+            Math::to_mathml("x^", false).unwrap_err(),
+            "`^` is missing an argument"
+        );
+        assert_eq!(
+            Math::to_mathml(r"\begin{matrix} a", false).unwrap_err(),
+            r"`\begin{matrix}` is missing its `\end{matrix}`"
+        );
+    }
+}
@@ -1,4 +1,7 @@
+use std::sync::Mutex;
+
 use colored::*;
+use once_cell::sync::Lazy;
 
 /// Logging message types
 pub enum Type {
@@ -14,14 +17,65 @@ pub enum Type {
     and it's just some `Ok`, `Err`, `exit(1)` over and over again... yeah.
 */
 
+// secrets (API tokens and such) that should never end up in the logs
+static SECRETS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Register a secret to be redacted from all logging messages
+pub fn redact_secret(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+
+    if let Ok(mut secrets) = SECRETS.lock() {
+        if !secrets.iter().any(|known| known == secret) {
+            secrets.push(secret.to_string())
+        }
+    }
+}
+
 /// Outputs logging messages
 pub fn push_message(log_type: Type, message: &str) {
     let prefix = match log_type {
         Type::Warning => format!("{}{}{}", "[".bold(), "WARN".bold().yellow(), "]".bold()),
         Type::Error => format!("{}{}{}", "[".bold(), "ERROR".bold().red(), "]".bold()),
         Type::Info => format!("{}{}{}", "[".bold(), "INFO".bold().cyan(), "]".bold()),
-        Type::Success => format!("{}{}{}", "[".bold(), "SUCCESS".bold().green(), "]".bold())
+        Type::Success => format!("{}{}{}", "[".bold(), "SUCCESS".bold().green(), "]".bold()),
     };
 
-    eprintln!("{}", format!("{} {}", prefix, message))
+    eprintln!("{} {}", prefix, redact(message))
+}
+
+/// Replace every registered secret in a message
+fn redact(message: &str) -> String {
+    let mut message = message.to_string();
+    if let Ok(secrets) = SECRETS.lock() {
+        for secret in secrets.iter() {
+            message = message.replace(secret.as_str(), "[REDACTED]");
+        }
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_registered_secrets() {
+        redact_secret("ghp_redactme123");
+
+        assert_eq!(
+            redact("Authorization: token ghp_redactme123 (ghp_redactme123)"),
+            "Authorization: token [REDACTED] ([REDACTED])"
+        );
+        assert_eq!(redact("nothing secret here"), "nothing secret here");
+    }
+
+    #[test]
+    fn never_registers_an_empty_secret() {
+        redact_secret("");
+
+        assert_eq!(redact("kept as is"), "kept as is");
+    }
 }
@@ -87,7 +87,7 @@ impl Utils {
             }
         }
 
-        for (page, page_data) in page_map {
+        for (page, page_data) in ParserUtils::sort_by_date(page_map) {
             let lastmod_datetime = match page_data.updated_at.parse::<DateTime<FixedOffset>>() {
                 Ok(parsed) => parsed,
                 Err(error) => {
@@ -101,7 +101,7 @@ impl Utils {
             };
 
             let url_builder = match UrlEntry::builder()
-                .loc(ParserUtils::join_url_path(&blog_url, page))
+                .loc(ParserUtils::join_url_path(blog_url, page))
                 .lastmod(lastmod_datetime)
                 .build()
             {
@@ -0,0 +1,340 @@
+use std::ops::Range;
+
+use html_escape::encode_text;
+use serde_json::Value;
+
+use crate::{config::RenderOptions, gist::Attachment, parsers::ParserUtils, toc::Toc};
+
+/// A Jupyter notebook (`*.blog.ipynb`) to render as a blog
+pub(crate) struct Notebook {
+    cells: Vec<Value>,
+    // language of the kernel, used to highlight code cells
+    language: String,
+}
+
+impl Notebook {
+    /// Parse the JSON of a notebook (only nbformat 4 and later have top level cells)
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let notebook: Value = serde_json::from_str(json).map_err(|error| error.to_string())?;
+
+        let cells = match notebook["cells"].as_array() {
+            Some(cells) => cells.clone(),
+            None => {
+                return Err(format!(
+                    "expected nbformat 4 or later (got {})",
+                    notebook["nbformat"]
+                ))
+            }
+        };
+
+        let metadata = &notebook["metadata"];
+        let language = metadata["kernelspec"]["language"]
+            .as_str()
+            .or_else(|| metadata["language_info"]["name"].as_str())
+            .unwrap_or("python")
+            .to_string();
+
+        Ok(Self { cells, language })
+    }
+
+    /// Markdown cells of the notebook
+    fn markdown_cells(&self) -> impl Iterator<Item = &Value> {
+        self.cells
+            .iter()
+            .filter(|cell| cell["cell_type"] == "markdown")
+    }
+
+    /// First level 1 heading of the markdown cells and where it is in its cell
+    pub fn title(&self) -> Option<(String, Range<usize>)> {
+        self.markdown_cells()
+            .find_map(|cell| ParserUtils::extract_title(&Self::text(&cell["source"])))
+    }
+
+    /// Render every cell of the notebook to HTML (optionally without the title heading), the
+    /// headings of every markdown cell go to the same table of contents and consecutive markdown
+    /// cells are parsed together, so reference links and footnotes work across them
+    pub fn to_html(
+        &self,
+        attachments: &[Attachment],
+        file: &str,
+        strip_title: bool,
+        options: &RenderOptions,
+        toc: &mut Toc,
+    ) -> String {
+        let mut html = String::new();
+        // markdown of the markdown cells since the last code cell
+        let mut markdown = String::new();
+
+        let mut title_stripped = !strip_title;
+
+        for (number, cell) in (1..).zip(&self.cells) {
+            let mut source = Self::text(&cell["source"]);
+
+            match cell["cell_type"].as_str() {
+                Some("markdown") => {
+                    if !title_stripped {
+                        if let Some((_, range)) = ParserUtils::extract_title(&source) {
+                            ParserUtils::blank_out(&mut source, range);
+                            title_stripped = true;
+                        }
+                    }
+
+                    let location = format!("cell {} of `{}`", number, file);
+                    let source = ParserUtils::render_math(&source, &location, options);
+                    markdown.push_str(&ParserUtils::expand_embeds(
+                        &source,
+                        attachments,
+                        file,
+                        options,
+                    ));
+                    // a cell never continues the paragraph of the previous one
+                    markdown.push_str("\n\n");
+                }
+                Some("code") => {
+                    html.push_str(&Self::markdown_to_html(&mut markdown, toc, options));
+
+                    // the same path as fenced code blocks, so the highlighting matches
+                    if !source.trim().is_empty() {
+                        let markdown = ParserUtils::fenced_code_block(&source, &self.language);
+                        html.push_str(&ParserUtils::parse_markdown_to_html(markdown, options));
+                    }
+
+                    for output in cell["outputs"].as_array().into_iter().flatten() {
+                        html.push_str(&Self::render_output(output));
+                    }
+                }
+                // raw cells are meant for nbconvert, not for readers
+                _ => (),
+            }
+        }
+
+        html.push_str(&Self::markdown_to_html(&mut markdown, toc, options));
+        html
+    }
+
+    /// Render and clear the markdown gathered from markdown cells
+    fn markdown_to_html(markdown: &mut String, toc: &mut Toc, options: &RenderOptions) -> String {
+        if markdown.is_empty() {
+            return String::new();
+        }
+
+        let html = ParserUtils::parse_markdown_with_toc(markdown, toc, options);
+        markdown.clear();
+        html
+    }
+
+    /// Render the output of a code cell
+    fn render_output(output: &Value) -> String {
+        match output["output_type"].as_str() {
+            Some("stream") => Self::text_output(&Self::text(&output["text"]), "output"),
+            Some("execute_result") | Some("display_data") => {
+                let data = &output["data"];
+
+                if !data["image/png"].is_null() {
+                    // base64 may be split over lines
+                    let image: String = Self::text(&data["image/png"]).split_whitespace().collect();
+                    format!(
+                        "<div class=\"output\"><img src=\"data:image/png;base64,{}\"></div>\n",
+                        image
+                    )
+                } else if !data["text/html"].is_null() {
+                    format!(
+                        "<div class=\"output\">{}</div>\n",
+                        Self::text(&data["text/html"])
+                    )
+                } else if !data["text/plain"].is_null() {
+                    Self::text_output(&Self::text(&data["text/plain"]), "output")
+                } else {
+                    String::new()
+                }
+            }
+            Some("error") => {
+                let traceback: Vec<&str> = output["traceback"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .collect();
+
+                let error = if traceback.is_empty() {
+                    format!(
+                        "{}: {}",
+                        output["ename"].as_str().unwrap_or_default(),
+                        output["evalue"].as_str().unwrap_or_default()
+                    )
+                } else {
+                    traceback.join("\n")
+                };
+                Self::text_output(&error, "output error")
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Plain text output, without the terminal colors
+    fn text_output(text: &str, class: &str) -> String {
+        format!(
+            "<pre class=\"{}\">{}</pre>\n",
+            class,
+            encode_text(&Self::strip_ansi(text))
+        )
+    }
+
+    /// Multiline strings are stored as a list of lines, or as a single string
+    fn text(value: &Value) -> String {
+        match value {
+            Value::String(text) => text.to_string(),
+            Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+            _ => String::new(),
+        }
+    }
+
+    /// Remove ANSI escape sequences (`ESC [ ... letter`), tracebacks and logs are full of them
+    fn strip_ansi(text: &str) -> String {
+        let mut stripped = String::with_capacity(text.len());
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            if c == '\u{1b}' {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            } else {
+                stripped.push(c);
+            }
+        }
+
+        stripped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+
+    fn notebook(cells: Value) -> Notebook {
+        Notebook::parse(&json!({ "cells": cells, "metadata": {} }).to_string()).unwrap()
+    }
+
+    fn options() -> RenderOptions {
+        Config::default().render_options()
+    }
+
+    fn markdown_cell(source: &str) -> Value {
+        json!({ "cell_type": "markdown", "source": source })
+    }
+
+    #[test]
+    fn rejects_old_notebook_formats() {
+        let error = Notebook::parse(r#"{ "nbformat": 3, "worksheets": [] }"#).err();
+
+        assert_eq!(
+            error.as_deref(),
+            Some("expected nbformat 4 or later (got 3)")
+        );
+    }
+
+    #[test]
+    fn finds_the_title_in_the_markdown_cells() {
+        let notebook = notebook(json!([
+            { "cell_type": "code", "source": "# not a title", "outputs": [] },
+            markdown_cell("Intro\n\n# Title\n"),
+        ]));
+
+        assert_eq!(notebook.title(), Some((String::from("Title"), 7..15)));
+    }
+
+    #[test]
+    fn strips_the_title_and_shares_the_toc() {
+        let notebook = notebook(json!([
+            markdown_cell("# Title\n\n## Setup\n"),
+            markdown_cell("## Setup\n"),
+        ]));
+        let mut toc = Toc::default();
+
+        let html = notebook.to_html(&[], "post.blog.ipynb", true, &options(), &mut toc);
+
+        assert_eq!(
+            html,
+            "<h2 id=\"setup\">Setup</h2>\n<h2 id=\"setup-1\">Setup</h2>\n"
+        );
+    }
+
+    #[test]
+    fn resolves_references_across_markdown_cells() {
+        let notebook = notebook(json!([
+            markdown_cell("See [the docs][docs] and the note[^1]."),
+            markdown_cell("[docs]: https://example.com\n"),
+            markdown_cell("[^1]: A footnote.\n"),
+        ]));
+
+        let html = notebook.to_html(
+            &[],
+            "post.blog.ipynb",
+            false,
+            &options(),
+            &mut Toc::default(),
+        );
+
+        assert!(html.contains("<a href=\"https://example.com\">the docs</a>"));
+        assert!(html.contains("<a href=\"#1\">1</a>"));
+        assert!(html.contains("<div class=\"footnote-definition\" id=\"1\">"));
+    }
+
+    #[test]
+    fn keeps_cells_in_separate_paragraphs() {
+        let notebook = notebook(json!([markdown_cell("One"), markdown_cell("Two")]));
+
+        let html = notebook.to_html(
+            &[],
+            "post.blog.ipynb",
+            false,
+            &options(),
+            &mut Toc::default(),
+        );
+
+        assert_eq!(html, "<p>One</p>\n<p>Two</p>\n");
+    }
+
+    #[test]
+    fn renders_code_cells_and_their_outputs() {
+        let notebook = notebook(json!([
+            markdown_cell("Before"),
+            {
+                "cell_type": "code",
+                "source": ["print(1)\n", "1 / 0"],
+                "outputs": [
+                    { "output_type": "stream", "text": ["1\n"] },
+                    {
+                        "output_type": "error",
+                        "ename": "ZeroDivisionError",
+                        "evalue": "division by zero",
+                        "traceback": ["\u{1b}[0;31mZeroDivisionError\u{1b}[0m: division by zero"]
+                    }
+                ]
+            },
+            markdown_cell("After"),
+            { "cell_type": "raw", "source": "{{ raw }}" },
+        ]));
+
+        let html = notebook.to_html(
+            &[],
+            "post.blog.ipynb",
+            false,
+            &options(),
+            &mut Toc::default(),
+        );
+
+        assert!(html.starts_with("<p>Before</p>\n<pre"));
+        assert!(html.contains("<pre class=\"output\">1\n</pre>\n"));
+        assert!(html.contains(
+            "<pre class=\"output error\">ZeroDivisionError: division by zero</pre>\n<p>After</p>\n"
+        ));
+        assert!(!html.contains("raw"));
+    }
+}
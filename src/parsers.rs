@@ -1,117 +1,355 @@
+use chrono::{DateTime, NaiveDate};
 use once_cell::sync::Lazy;
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
 use std::process::exit;
 
-use pulldown_cmark::{html, Options, Parser};
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag};
+use similar::{ChangeTag, TextDiff};
 
-use syntect::highlighting::ThemeSet;
-use syntect::html::highlighted_html_for_string;
-use syntect::parsing::SyntaxSet;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{
+    append_highlighted_html_for_styled_line, css_for_theme_with_class_style,
+    line_tokens_to_classed_spans, ClassStyle, IncludeBackground,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
 
-use html_escape::{encode_text, decode_html_entities};
-use lol_html::html_content::ContentType;
-use lol_html::{rewrite_str, text, RewriteStrSettings};
+use html_escape::{decode_html_entities, encode_double_quoted_attribute, encode_text};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
-use crate::messages::{push_message, Type};
+use crate::{
+    config::RenderOptions,
+    gist::{Attachment, GistPage, PageMetadata},
+    math::Math,
+    messages::{push_message, Type},
+    table::Table,
+    toc::Toc,
+};
+
+// bundled syntaxes, along with the custom ones in `syntaxes/`
+static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(|| {
+    let mut syntax_set = SyntaxSet::load_defaults_newlines().into_builder();
+    syntax_set.add_from_folder("syntaxes", true).ok();
+    syntax_set.build()
+});
+
+// bundled themes, along with the `.tmTheme` files in `themes/` (named after the file)
+static THEME_SET: Lazy<ThemeSet> = Lazy::new(|| {
+    let mut theme_set = ThemeSet::load_defaults();
+    if Path::new("themes").is_dir() {
+        if let Err(error) = theme_set.add_from_folder("themes") {
+            let message = format!("Failed to load the themes in `themes/`: \n\t{}", error);
+            push_message(Type::Warning, &message);
+        }
+    }
+    theme_set
+});
+
+// characters left as is in a URL path segment (RFC 3986 unreserved)
+static PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// prefixed so the highlighting classes don't clash with the templates' own
+static HIGHLIGHT_CLASS_PREFIX: &str = "syn-";
+static HIGHLIGHT_CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "syn-" };
+
+/// Language and attributes of a fenced code block
+#[derive(Default)]
+struct FenceInfo {
+    lang: String,
+    line_numbers: bool,
+    highlighted_lines: Vec<RangeInclusive<usize>>,
+}
+
+// marks the code blocks of comments while they're sanitized (the object replacement character)
+static CODE_PLACEHOLDER: &str = "\u{fffc}";
+
+// tags that don't end a word, for the plain text of rendered HTML
+static INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "code", "del", "em", "i", "kbd", "mark", "s", "small", "span", "strong",
+    "sub", "sup",
+];
 
 pub(crate) struct ParserUtils;
 
 impl ParserUtils {
-    /// Highlight source code snippets in the parsed HTML
-    fn highlight_source_code(html: &str) -> String {
-        let mut lang_set: Vec<String> = Vec::new();
-
-        // the lazy but quick html parsing
-        for segment in html.split("\n") {
-            if segment.contains("<code class=\"language-") {
-                for item in segment.split("\"") {
-                    if item.contains("language") {
-                        lang_set.push(item.replace("language-", ""));
-                    }
+    /// Language and attributes of a fenced code block from its info string
+    /// (` ```rust,ignore ` is `rust`, ` ```rust {linenos, hl=3-5} ` numbers the lines and marks 3 to 5)
+    fn parse_fence_info(info: &str) -> FenceInfo {
+        let lang = info
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        let mut fence = FenceInfo {
+            lang,
+            ..FenceInfo::default()
+        };
+
+        let attributes = match (info.find('{'), info.rfind('}')) {
+            (Some(start), Some(end)) if start < end => &info[start + 1..end],
+            _ => return fence,
+        };
+
+        // `hl` takes a list of lines and ranges, `hl=1,3-5`
+        let mut in_hl = false;
+
+        for attribute in attributes
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|attribute| !attribute.is_empty())
+        {
+            let lines = if attribute == "linenos" {
+                fence.line_numbers = true;
+                in_hl = false;
+                continue;
+            } else if let Some(lines) = attribute.strip_prefix("hl=") {
+                in_hl = true;
+                lines
+            } else if in_hl && attribute.starts_with(|c: char| c.is_ascii_digit()) {
+                attribute
+            } else {
+                let message = format!("Ignored unknown code block attribute `{}`.", attribute);
+                push_message(Type::Warning, &message);
+                continue;
+            };
+
+            match Self::parse_line_range(lines) {
+                Some(range) => fence.highlighted_lines.push(range),
+                None => {
+                    let message = format!(
+                        "Ignored invalid lines `{}` in a code block's `hl` attribute.",
+                        lines
+                    );
+                    push_message(Type::Warning, &message);
                 }
-            } else if segment.contains("<code>") {
-                lang_set.push("txt".into())
             }
         }
 
-        if lang_set.len() != 0 {
-            static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(|| {
-                let mut syntax_set = SyntaxSet::load_defaults_newlines().into_builder();
-                syntax_set.add_from_folder("syntaxes", true).ok();
-                syntax_set.build()
-            });
+        fence
+    }
 
-            let theme_set = ThemeSet::load_defaults();
-            let theme = &theme_set.themes["InspiredGitHub"];
-
-            let mut code_elem_idx = 0;
-
-            let rewrite = match rewrite_str(
-                &html.replace("&quot;", "\""),
-                RewriteStrSettings {
-                    element_content_handlers: vec![text!("code", |chunk| {
-                        if code_elem_idx > lang_set.len() - 1 {
-                            let escape = encode_text(chunk.as_str()).to_string();
-                            chunk.replace(&escape, ContentType::Text)
-                        } else {
-                            let lang_syntax =
-                                match SyntaxSet::find_syntax_by_token(&SYNTAX_SET, &lang_set[code_elem_idx]) {
-                                    Some(lang_syntax) => lang_syntax,
-                                    None => {
-                                        let message = format!(
-                                            "No syntax highlighting spec found for `{}`.",
-                                            &lang_set[code_elem_idx]
-                                        );
-                                        push_message(Type::Warning, &message);
-                                        match SyntaxSet::find_syntax_by_token(&SYNTAX_SET, "txt") {
-                                            Some(lang_syntax) => lang_syntax,
-                                            _ => unreachable!()
-                                        }
-                                    }
-                                };
-
-                            chunk.replace(
-                                &highlighted_html_for_string(
-                                    &chunk.as_str(),
-                                    &SYNTAX_SET,
-                                    lang_syntax,
-                                    theme,
-                                ),
-                                ContentType::Text,
-                            );
-                        };
+    /// A line (`3`) or a range of lines (`3-5`), counted from 1
+    fn parse_line_range(lines: &str) -> Option<RangeInclusive<usize>> {
+        let (start, end) = match lines.split_once('-') {
+            Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+            None => {
+                let line = lines.parse().ok()?;
+                (line, line)
+            }
+        };
 
-                        code_elem_idx += 1;
+        if start == 0 || start > end {
+            return None;
+        }
 
-                        Ok(())
-                    })],
-                    ..RewriteStrSettings::default()
-                },
-            ) {
-                Ok(rewrite) => rewrite,
-                Err(error) => {
-                    let message = format!(
-                        "Failed to highlight syntax inside HTML document due to: \n\t{}",
-                        error
-                    );
-                    push_message(Type::Error, &message);
-                    exit(1)
+        Some(start..=end)
+    }
+
+    /// Highlight the source code of a code block
+    fn highlight_code_block(code: &str, fence: &FenceInfo, options: &RenderOptions) -> String {
+        let lang = &fence.lang;
+
+        let syntax = if lang.is_empty() {
+            SYNTAX_SET.find_syntax_plain_text()
+        } else {
+            match SYNTAX_SET.find_syntax_by_token(lang) {
+                Some(syntax) => syntax,
+                None => {
+                    let message = format!("No syntax highlighting spec found for `{}`.", lang);
+                    push_message(Type::Warning, &message);
+                    SYNTAX_SET.find_syntax_plain_text()
                 }
-            };
+            }
+        };
 
-            // return a sanitized output
-            // is there a better way to go about this? i suck at html.
-            decode_html_entities(&rewrite)
-                .replace("&amp;", "&") // encoding lives happily ever after
-                .replace("<pre style=\"background-color:#ffffff;\">\n</pre>", "") // anomaly
-                .replace("<code><pre style=\"background-color:#ffffff;\">", "<code>") // next anomaly
+        let lang_class = if lang.is_empty() {
+            String::new()
         } else {
-            html.to_string()
+            format!(
+                " class=\"language-{}\"",
+                encode_double_quoted_attribute(lang)
+            )
+        };
+
+        // styled by `syntax.css` instead of inline styles
+        let (mut html, lines) = if options.class_highlighting {
+            (
+                format!(
+                    "<pre class=\"{}code\"><code{}>",
+                    HIGHLIGHT_CLASS_PREFIX, lang_class
+                ),
+                Self::classed_lines(code, syntax),
+            )
+        } else {
+            let theme = options.syntax_theme;
+            let background = theme.settings.background.unwrap_or(Color::WHITE);
+
+            (
+                format!(
+                    "<pre style=\"background-color:#{:02x}{:02x}{:02x};\"><code{}>",
+                    background.r, background.g, background.b, lang_class
+                ),
+                Self::styled_lines(code, syntax, theme, background),
+            )
+        };
+
+        // lines are only wrapped when they have to be numbered or marked
+        let wrap_lines = fence.line_numbers || !fence.highlighted_lines.is_empty();
+
+        for (number, line) in (1..).zip(lines) {
+            if !wrap_lines {
+                html.push_str(&line);
+                continue;
+            }
+
+            let marked = fence
+                .highlighted_lines
+                .iter()
+                .any(|range| range.contains(&number));
+
+            if marked {
+                html.push_str(&format!(
+                    "<span class=\"{0}code-line {0}code-hl\">",
+                    HIGHLIGHT_CLASS_PREFIX
+                ));
+            } else {
+                html.push_str(&format!(
+                    "<span class=\"{}code-line\">",
+                    HIGHLIGHT_CLASS_PREFIX
+                ));
+            }
+            if fence.line_numbers {
+                html.push_str(&format!(
+                    "<span class=\"{}code-lineno\">{}</span>",
+                    HIGHLIGHT_CLASS_PREFIX, number
+                ));
+            }
+            html.push_str(&line);
+            html.push_str("</span>");
         }
+
+        html.push_str("</code></pre>\n");
+        html
     }
 
-    /// Converts gist's raw markdown to HTML
-    pub fn parse_markdown_to_html(raw_markdown: String) -> String {
+    /// Lines of code highlighted with inline styles (syntect escapes the code as it goes)
+    fn styled_lines(
+        code: &str,
+        syntax: &SyntaxReference,
+        theme: &Theme,
+        background: Color,
+    ) -> Vec<String> {
+        let mut highlighter = HighlightLines::new(syntax, theme);
+
+        LinesWithEndings::from(code)
+            .map(|line| {
+                let regions = highlighter.highlight(line, &SYNTAX_SET);
+                let mut html = String::new();
+                append_highlighted_html_for_styled_line(
+                    &regions,
+                    IncludeBackground::IfDifferent(background),
+                    &mut html,
+                );
+                html
+            })
+            .collect()
+    }
+
+    /// Lines of code highlighted with classes, the scopes spanning several lines are closed at
+    /// the end of each line and opened again on the next so every line stands on its own
+    fn classed_lines(code: &str, syntax: &SyntaxReference) -> Vec<String> {
+        let mut parse_state = ParseState::new(syntax);
+        let mut scope_stack = ScopeStack::new();
+
+        LinesWithEndings::from(code)
+            .map(|line| {
+                let ops = parse_state.parse_line(line, &SYNTAX_SET);
+
+                let mut html: String = scope_stack
+                    .as_slice()
+                    .iter()
+                    .map(|scope| {
+                        let classes: Vec<String> = scope
+                            .build_string()
+                            .split('.')
+                            .map(|atom| format!("{}{}", HIGHLIGHT_CLASS_PREFIX, atom))
+                            .collect();
+                        format!("<span class=\"{}\">", classes.join(" "))
+                    })
+                    .collect();
+
+                let (line_html, _) = line_tokens_to_classed_spans(
+                    line,
+                    &ops,
+                    HIGHLIGHT_CLASS_STYLE,
+                    &mut scope_stack,
+                );
+                html.push_str(&line_html);
+                html.push_str(&"</span>".repeat(scope_stack.len()));
+                html
+            })
+            .collect()
+    }
+
+    /// Stylesheet for class based highlighting, with a dark variant if one is configured
+    /// (`None` when code is styled inline)
+    pub fn syntax_css(options: &RenderOptions) -> Option<String> {
+        if !options.class_highlighting {
+            return None;
+        }
+
+        let mut css = css_for_theme_with_class_style(options.syntax_theme, HIGHLIGHT_CLASS_STYLE);
+
+        if let Some(dark_theme) = options.syntax_dark_theme {
+            css.push_str(&format!(
+                "\n@media (prefers-color-scheme: dark) {{\n{}}}\n",
+                css_for_theme_with_class_style(dark_theme, HIGHLIGHT_CLASS_STYLE)
+            ));
+        }
+
+        Some(css)
+    }
+
+    /// Whether code is highlighted with classes (`syntax.css`) or inline styles, from
+    /// `syntax_highlighting`
+    pub fn class_highlighting(mode: &str) -> bool {
+        match mode {
+            "inline" => false,
+            "classes" => true,
+            mode => {
+                let message = format!(
+                    "Unknown `syntax_highlighting` \"{}\" (expected \"inline\" or \"classes\").",
+                    mode
+                );
+                push_message(Type::Error, &message);
+                exit(1)
+            }
+        }
+    }
+
+    /// Theme configured under `option`, the error lists the available ones
+    pub fn theme(option: &str, name: &str) -> Result<&'static Theme, String> {
+        THEME_SET.themes.get(name).ok_or_else(|| {
+            let available: Vec<&str> = THEME_SET.themes.keys().map(String::as_str).collect();
+            format!(
+                "Unknown `{}` \"{}\" (available: {}).",
+                option,
+                name,
+                available.join(", ")
+            )
+        })
+    }
+
+    /// Markdown extensions gisture enables (GitHub flavored elements)
+    fn markdown_options() -> Options {
         static OPTIONS: Lazy<pulldown_cmark::Options> = Lazy::new(|| {
             let mut options = Options::empty();
             options.insert(Options::ENABLE_STRIKETHROUGH);
@@ -122,11 +360,371 @@ impl ParserUtils {
             options
         });
 
-        let parser = Parser::new_ext(&raw_markdown, *OPTIONS);
+        *OPTIONS
+    }
+
+    /// Find the first level 1 heading (`# Title` or `Title\n===`) along with where it is
+    /// in the markdown, so it can be cut out of the body
+    pub fn extract_title(markdown: &str) -> Option<(String, Range<usize>)> {
+        let mut heading: Option<Range<usize>> = None;
+        let mut title = String::new();
+
+        for (event, range) in Parser::new_ext(markdown, Self::markdown_options()).into_offset_iter()
+        {
+            match event {
+                Event::Start(Tag::Heading(1)) if heading.is_none() => heading = Some(range),
+                Event::End(Tag::Heading(1)) => {
+                    if let Some(heading) = heading {
+                        return Some((title.trim().to_string(), heading));
+                    }
+                }
+                // the title is plain text, formatting is dropped
+                Event::Text(text) | Event::Code(text) if heading.is_some() => title.push_str(&text),
+                Event::SoftBreak | Event::HardBreak if heading.is_some() => title.push(' '),
+                _ => (),
+            }
+        }
+
+        None
+    }
+
+    /// Converts gist's raw markdown to HTML
+    pub fn parse_markdown_to_html(raw_markdown: String, options: &RenderOptions) -> String {
+        Self::parse_markdown_with_toc(&raw_markdown, &mut Toc::default(), options)
+    }
+
+    /// Converts markdown to HTML, the headings get IDs and are added to the table of contents
+    pub fn parse_markdown_with_toc(
+        raw_markdown: &str,
+        toc: &mut Toc,
+        options: &RenderOptions,
+    ) -> String {
+        Self::render_markdown(raw_markdown, toc, |code, fence| {
+            Self::highlight_code_block(code, fence, options)
+        })
+    }
+
+    /// Converts markdown to HTML with the code blocks rendered by `code_block`
+    fn render_markdown<F>(raw_markdown: &str, toc: &mut Toc, mut code_block_html: F) -> String
+    where
+        F: FnMut(&str, &FenceInfo) -> String,
+    {
+        // fence attributes and source of the code block being parsed
+        let mut code_block: Option<(FenceInfo, String)> = None;
+
+        // level and content of the heading being parsed
+        let mut heading: Option<(u32, Vec<Event>)> = None;
+
+        // code blocks are highlighted in place, inline code is left alone
+        let parser =
+            Parser::new_ext(raw_markdown, Self::markdown_options()).filter_map(
+                |event| match event {
+                    Event::Start(Tag::CodeBlock(kind)) => {
+                        let fence = match kind {
+                            CodeBlockKind::Fenced(info) => Self::parse_fence_info(&info),
+                            CodeBlockKind::Indented => FenceInfo::default(),
+                        };
+                        code_block = Some((fence, String::new()));
+                        None
+                    }
+                    Event::Text(text) if code_block.is_some() => {
+                        if let Some((_, code)) = code_block.as_mut() {
+                            code.push_str(&text);
+                        }
+                        None
+                    }
+                    Event::End(Tag::CodeBlock(_)) => code_block
+                        .take()
+                        .map(|(fence, code)| Event::Html(code_block_html(&code, &fence).into())),
+                    Event::Start(Tag::Heading(level)) => {
+                        heading = Some((level, Vec::new()));
+                        None
+                    }
+                    Event::End(Tag::Heading(_)) => heading
+                        .take()
+                        .map(|(level, content)| Event::Html(toc.heading(level, content).into())),
+                    event => match heading.as_mut() {
+                        Some((_, content)) => {
+                            content.push(event);
+                            None
+                        }
+                        None => Some(event),
+                    },
+                },
+            );
+
         let mut html_output = String::new();
         html::push_html(&mut html_output, parser);
 
-        Self::highlight_source_code(&html_output)
+        html_output
+    }
+
+    /// Text of rendered HTML, without the tags and entities
+    pub fn plain_text(html: &str) -> String {
+        let mut text = String::with_capacity(html.len());
+        // the tag being skipped, if any
+        let mut tag: Option<String> = None;
+        // rendered math reads as a single word, like the LaTeX it came from
+        let mut in_math = false;
+
+        for c in html.chars() {
+            match (c, tag.as_mut()) {
+                ('<', None) => tag = Some(String::new()),
+                ('>', Some(name)) => {
+                    // block tags separate words (`<td>a</td><td>b</td>`), inline ones don't
+                    let closing = name.starts_with('/');
+                    let name = name
+                        .trim_start_matches('/')
+                        .chars()
+                        .take_while(char::is_ascii_alphanumeric)
+                        .collect::<String>()
+                        .to_lowercase();
+                    if name == "math" {
+                        in_math = !closing;
+                    } else if !in_math && !INLINE_TAGS.contains(&name.as_str()) {
+                        text.push(' ');
+                    }
+                    tag = None;
+                }
+                (c, Some(name)) => name.push(c),
+                (c, None) => text.push(c),
+            }
+        }
+
+        let text: Vec<&str> = text.split_whitespace().collect();
+        decode_html_entities(&text.join(" ")).to_string()
+    }
+
+    /// Number of words in rendered HTML (punctuation on its own isn't a word)
+    pub fn word_count(html: &str) -> usize {
+        Self::plain_text(html)
+            .split_whitespace()
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .count()
+    }
+
+    /// Plain text excerpt of rendered HTML: the paragraphs before a `<!-- more -->` marker, or
+    /// else the first paragraph
+    pub fn excerpt(html: &str) -> String {
+        let marker = ["<!-- more -->", "<!--more-->"]
+            .iter()
+            .find_map(|marker| html.find(marker));
+
+        // the marker may be in the middle of a paragraph
+        let mut paragraphs = html[..marker.unwrap_or(html.len())]
+            .split("<p>")
+            .skip(1)
+            .map(|paragraph| {
+                let paragraph = paragraph.split("</p>").next().unwrap_or_default();
+                Self::plain_text(paragraph)
+            })
+            .filter(|paragraph| !paragraph.is_empty());
+
+        match marker {
+            Some(_) => paragraphs.collect::<Vec<_>>().join(" "),
+            None => paragraphs.next().unwrap_or_default(),
+        }
+    }
+
+    /// Remove part of the markdown, keeping its line breaks so the lines after it keep their
+    /// numbers (for the warnings about them)
+    pub fn blank_out(markdown: &mut String, range: Range<usize>) {
+        let line_breaks = "\n".repeat(markdown[range.clone()].matches('\n').count());
+        markdown.replace_range(range, &line_breaks);
+    }
+
+    /// Render the `$...$` (inline) and `$$...$$` (block) math of markdown to MathML, code and
+    /// HTML are left alone and so are invalid expressions (with a warning naming the `location`)
+    pub fn render_math(markdown: &str, location: &str, options: &RenderOptions) -> String {
+        if !options.math {
+            return markdown.to_string();
+        }
+
+        let skipped: Vec<Range<usize>> = Parser::new_ext(markdown, Self::markdown_options())
+            .into_offset_iter()
+            .filter_map(|(event, range)| match event {
+                Event::Start(Tag::CodeBlock(_)) | Event::Code(_) | Event::Html(_) => Some(range),
+                _ => None,
+            })
+            .collect();
+
+        let mut rendered = String::with_capacity(markdown.len());
+        // end of the markdown copied to `rendered` so far
+        let mut copied = 0;
+        let mut index = 0;
+
+        while index < markdown.len() {
+            if let Some(range) = skipped.iter().find(|range| range.contains(&index)) {
+                index = range.end;
+                continue;
+            }
+
+            match markdown.as_bytes()[index] {
+                // `\$` is a dollar sign
+                b'\\' => index += 2,
+                b'$' => match Self::math_span(markdown, index, &skipped) {
+                    Some((end, latex, display)) => {
+                        match Math::to_mathml(latex, display) {
+                            Ok(mathml) => {
+                                rendered.push_str(&markdown[copied..index]);
+                                rendered.push_str(&mathml);
+                                copied = end;
+                            }
+                            Err(error) => {
+                                let line = markdown[..index].matches('\n').count() + 1;
+                                let message = format!(
+                                    "Failed to render the math `{}` on line {} of {}: \n\t{} (IGNORED)",
+                                    &markdown[index..end],
+                                    line,
+                                    location,
+                                    error
+                                );
+                                push_message(Type::Warning, &message);
+                            }
+                        }
+                        index = end;
+                    }
+                    // an unclosed `$$` isn't the start of inline math either
+                    None if markdown[index..].starts_with("$$") => index += 2,
+                    None => index += 1,
+                },
+                _ => index += 1,
+            }
+        }
+
+        rendered.push_str(&markdown[copied..]);
+        rendered
+    }
+
+    /// End, LaTeX and kind (block or not) of the math starting at `start`, like pandoc an
+    /// inline `$` has to be followed by a non-space and closed by a `$` after a non-space that
+    /// isn't followed by a digit (so `$5 and $10` is no math)
+    fn math_span<'a>(
+        markdown: &'a str,
+        start: usize,
+        skipped: &[Range<usize>],
+    ) -> Option<(usize, &'a str, bool)> {
+        // math doesn't run into code or HTML
+        let limit = skipped
+            .iter()
+            .map(|range| range.start)
+            .filter(|&skipped_start| skipped_start > start)
+            .min()
+            .unwrap_or(markdown.len());
+        let rest = &markdown[..limit];
+
+        if rest[start..].starts_with("$$") {
+            let latex_start = start + 2;
+            let latex_end = latex_start + rest[latex_start..].find("$$")?;
+            let latex = &rest[latex_start..latex_end];
+
+            return match latex.trim() {
+                "" => None,
+                _ => Some((latex_end + 2, latex, true)),
+            };
+        }
+
+        let latex_start = start + 1;
+        if rest[latex_start..].chars().next()?.is_whitespace() {
+            return None;
+        }
+
+        let mut previous = '$';
+        let mut chars = rest[latex_start..].char_indices().peekable();
+
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                // inline math stays in its paragraph
+                '\n' if rest[latex_start + offset + 1..]
+                    .lines()
+                    .next()
+                    .is_none_or(|line| line.trim().is_empty()) =>
+                {
+                    return None
+                }
+                '$' if !previous.is_whitespace()
+                    && !chars.peek().is_some_and(|(_, next)| next.is_ascii_digit()) =>
+                {
+                    let latex_end = latex_start + offset;
+                    return Some((latex_end + 1, &rest[latex_start..latex_end], false));
+                }
+                _ => (),
+            }
+            previous = c;
+        }
+
+        None
+    }
+
+    /// Converts markdown written by anyone (gist comments) to HTML without scripts and such
+    pub fn parse_untrusted_markdown_to_html(
+        raw_markdown: String,
+        options: &RenderOptions,
+    ) -> String {
+        // code blocks stand in as placeholders while the rest is sanitized, so the only styles
+        // left are the ones of the highlighted code (a commenter can't forge a placeholder)
+        let raw_markdown = raw_markdown.replace(CODE_PLACEHOLDER, "");
+        let mut code_blocks: Vec<String> = Vec::new();
+
+        let html_output =
+            Self::render_markdown(&raw_markdown, &mut Toc::default(), |code, fence| {
+                code_blocks.push(Self::highlight_code_block(code, fence, options));
+                Self::code_placeholder(code_blocks.len() - 1)
+            });
+
+        let mut sanitized = ammonia::clean(&html_output);
+        for (index, code_block) in code_blocks.iter().enumerate() {
+            sanitized = sanitized.replacen(&Self::code_placeholder(index), code_block, 1);
+        }
+
+        sanitized
+    }
+
+    /// Stand-in for the code block at `index` of an untrusted markdown
+    fn code_placeholder(index: usize) -> String {
+        format!("<p>{}{}{}</p>\n", CODE_PLACEHOLDER, index, CODE_PLACEHOLDER)
+    }
+
+    /// Render a line diff between two versions of a markdown file (with +/- line counts)
+    pub fn render_diff(old: &str, new: &str) -> (String, usize, usize) {
+        let diff = TextDiff::from_lines(old, new);
+
+        let mut diff_html = String::from("<pre class=\"diff\">");
+        let (mut additions, mut deletions) = (0, 0);
+
+        // only the changed lines with a few lines of context around them
+        for (idx, group) in diff.grouped_ops(3).iter().enumerate() {
+            if idx > 0 {
+                diff_html.push_str("<span class=\"diff-hunk\">…</span>\n");
+            }
+
+            for op in group {
+                for change in diff.iter_changes(op) {
+                    let line = encode_text(change.value().trim_end_matches('\n'));
+
+                    let rendered = match change.tag() {
+                        ChangeTag::Insert => {
+                            additions += 1;
+                            format!("<ins>+ {}</ins>\n", line)
+                        }
+                        ChangeTag::Delete => {
+                            deletions += 1;
+                            format!("<del>- {}</del>\n", line)
+                        }
+                        ChangeTag::Equal => format!("  {}\n", line),
+                    };
+
+                    diff_html.push_str(&rendered);
+                }
+            }
+        }
+
+        diff_html.push_str("</pre>");
+
+        (diff_html, additions, deletions)
     }
 
     /// For handling trailing slashes on URLs
@@ -138,14 +736,958 @@ impl ParserUtils {
         }
     }
 
-    /// RFC 3339 date-time parsing and sorting
-    pub fn _sort_by_date(_page_metadata: HashMap<String, String>) -> HashMap<i32, String> {
+    /// URL of an attachment saved next to its page (gist filenames can have spaces, `#`, `?`...)
+    pub fn attachment_url(page: &str, file: &str) -> String {
+        format!(
+            "/{}/{}",
+            utf8_percent_encode(page, PATH_SEGMENT),
+            utf8_percent_encode(file, PATH_SEGMENT)
+        )
+    }
+
+    /// Ranges of the code blocks and code spans of markdown
+    fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+        Parser::new_ext(markdown, Self::markdown_options())
+            .into_offset_iter()
+            .filter_map(|(event, range)| match event {
+                Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Find a `<!-- gisture:revision <sha> -->` marker pinning a gist to a revision
+    /// (markers in code are examples of them)
+    pub fn parse_revision_marker(markdown: &str) -> Option<String> {
+        let marker = "<!-- gisture:revision ";
+        let code = Self::code_ranges(markdown);
+
+        let start = markdown
+            .match_indices(marker)
+            .map(|(start, _)| start)
+            .find(|start| !code.iter().any(|range| range.contains(start)))?
+            + marker.len();
+        let end = start + markdown[start..].find("-->")?;
+
+        let revision = markdown[start..end].trim();
+
+        if !revision.is_empty() && revision.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some(revision.to_string())
+        } else {
+            let message = format!("Ignored invalid revision marker `{}`.", revision);
+            push_message(Type::Warning, &message);
+            None
+        }
+    }
+
+    /// Inline the `{{embed "file"}}` directives of a blog as fenced code blocks of its attachments
+    /// (or tables, for CSV/TSV files)
+    pub fn expand_embeds(
+        markdown: &str,
+        attachments: &[Attachment],
+        blog_file: &str,
+        options: &RenderOptions,
+    ) -> String {
+        // directives in code are examples of them, not embeds
+        let skipped = Self::code_ranges(markdown);
+
+        let mut expanded = String::with_capacity(markdown.len());
+        // end of the markdown copied to `expanded` so far
+        let mut copied = 0;
+        let mut search = 0;
+
+        while let Some(offset) = markdown[search..].find("{{") {
+            let start = search + offset;
+
+            if let Some(range) = skipped.iter().find(|range| range.contains(&start)) {
+                search = range.end;
+                continue;
+            }
+
+            let (file, directive_len) = match Self::parse_embed_directive(&markdown[start..]) {
+                Some(directive) => directive,
+                None => {
+                    // not a directive, leave it alone
+                    search = start + 2;
+                    continue;
+                }
+            };
+            search = start + directive_len;
+
+            // directives that can't be resolved stay in the text
+            let block = match Self::embedded_block(file, attachments, blog_file, options) {
+                Some(block) => block,
+                None => continue,
+            };
+
+            // inside a list item the block has to be indented like the item's content
+            let line_start = markdown[..start].rfind('\n').map_or(0, |index| index + 1);
+            let indent = Self::content_indent(&markdown[line_start..start]);
+
+            expanded.push_str(&markdown[copied..start]);
+
+            // the block has to start on a line of its own
+            expanded.truncate(expanded.trim_end_matches([' ', '\t']).len());
+            if !expanded.is_empty() && !expanded.ends_with('\n') {
+                expanded.push('\n');
+            }
+
+            for line in block.lines() {
+                if !line.is_empty() {
+                    expanded.push_str(&indent);
+                }
+                expanded.push_str(line);
+                expanded.push('\n');
+            }
+
+            copied = search;
+        }
+
+        expanded.push_str(&markdown[copied..]);
+        expanded
+    }
+
+    /// Markdown of an embedded attachment (`None` with a warning if it can't be embedded)
+    fn embedded_block(
+        file: &str,
+        attachments: &[Attachment],
+        blog_file: &str,
+        options: &RenderOptions,
+    ) -> Option<String> {
+        let content = attachments
+            .iter()
+            .find(|attachment| attachment.file == file)
+            .map(|attachment| std::str::from_utf8(&attachment.content));
+
+        let content = match content {
+            Some(Ok(content)) => content,
+            Some(Err(_)) => {
+                let message = format!(
+                    "`{}` embeds `{}` which isn't a text file. (IGNORED)",
+                    blog_file, file
+                );
+                push_message(Type::Warning, &message);
+                return None;
+            }
+            None => {
+                let message = format!(
+                    "`{}` embeds `{}` which isn't in its gist. (IGNORED)",
+                    blog_file, file
+                );
+                push_message(Type::Warning, &message);
+                return None;
+            }
+        };
+
+        // datasets read better as a table than as a listing, a blank line ends the HTML block
+        if let Some(delimiter) = Table::delimiter(file) {
+            return Some(format!(
+                "{}\n",
+                Table::to_html(content, delimiter, file, options)
+            ));
+        }
+
+        // the extension doubles as the syntax highlighting token
+        let lang = Path::new(file)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        Some(Self::fenced_code_block(content, &lang))
+    }
+
+    /// Indentation of the content on a line, given the text before a position in it (the
+    /// content of a list item lines up after its marker, `- ` or `1. `)
+    fn content_indent(line: &str) -> String {
+        let content = line.trim_start();
+        let leading = &line[..line.len() - content.len()];
+
+        let marker_len = if content.starts_with(['-', '*', '+']) {
+            1
+        } else {
+            let digits = content.chars().take_while(char::is_ascii_digit).count();
+            if digits > 0 && content[digits..].starts_with(['.', ')']) {
+                digits + 1
+            } else {
+                0
+            }
+        };
+
+        let spaces =
+            content[marker_len..].len() - content[marker_len..].trim_start_matches(' ').len();
+
+        if marker_len == 0 || spaces == 0 {
+            leading.to_string()
+        } else {
+            format!("{}{}", leading, " ".repeat(marker_len + spaces))
+        }
+    }
+
+    /// Wrap source code in a fenced code block of the given language
+    pub fn fenced_code_block(code: &str, lang: &str) -> String {
+        // the fence has to be longer than any run of backticks inside the code
+        let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+
+        format!(
+            "{}{}\n{}\n{}\n",
+            fence,
+            lang,
+            code.trim_end_matches('\n'),
+            fence
+        )
+    }
+
+    /// Parse an `{{embed "file"}}` directive at the start of the text into the
+    /// embedded file name and the length of the directive
+    fn parse_embed_directive(text: &str) -> Option<(&str, usize)> {
+        let inner = text.strip_prefix("{{")?;
+        let end = inner.find("}}")?;
+
+        let arguments = inner[..end].trim().strip_prefix("embed")?;
+        if !arguments.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        let file = arguments.trim().strip_prefix('"')?.strip_suffix('"')?;
+        if file.is_empty() || file.contains('"') {
+            return None;
+        }
+
+        Some((file, end + 4))
+    }
+
+    /// Split the metadata block off the top of a blog's markdown, an HTML comment
+    /// so it stays hidden on GitHub too:
+    ///
+    /// ```text
+    /// <!-- gisture
+    /// slug: hello-world
+    /// tags: rust, web
+    /// -->
+    /// ```
+    pub fn parse_metadata_block(markdown: &str, file: &str) -> (PageMetadata, String) {
+        let mut metadata = PageMetadata::default();
+
+        let content = markdown.trim_start_matches('\u{feff}').trim_start();
+
+        let block = match content
+            .strip_prefix("<!-- gisture")
+            .filter(|rest| rest.starts_with('\n') || rest.starts_with("\r\n"))
+        {
+            Some(block) => block,
+            None => return (metadata, markdown.to_string()),
+        };
+
+        let (block, rest) = match block.find("-->") {
+            Some(end) => (&block[..end], &block[end + 3..]),
+            None => {
+                let message = format!("`{}` has an unclosed metadata block. (IGNORED)", file);
+                push_message(Type::Warning, &message);
+                return (metadata, markdown.to_string());
+            }
+        };
+
+        for line in block.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim().to_string()),
+                None => {
+                    let message = format!("Ignored metadata line `{}` in `{}`.", line, file);
+                    push_message(Type::Warning, &message);
+                    continue;
+                }
+            };
+
+            match key {
+                "title" => metadata.title = Some(value),
+                "description" => metadata.description = Some(value),
+                "slug" => {
+                    // it ends up as a directory name, dots would let it take the place of the
+                    // generated files (`index.html`, `sitemap.xml`...)
+                    let valid = !value.is_empty()
+                        && value
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_'));
+
+                    if valid {
+                        metadata.slug = Some(value);
+                    } else {
+                        let message = format!("Ignored invalid slug `{}` in `{}`.", value, file);
+                        push_message(Type::Warning, &message);
+                    }
+                }
+                "date" => match Self::parse_date(&value) {
+                    Some(date) => metadata.date = Some(date),
+                    None => {
+                        let message = format!(
+                            "Ignored invalid date `{}` in `{}` (expected `YYYY-MM-DD` or RFC 3339).",
+                            value, file
+                        );
+                        push_message(Type::Warning, &message);
+                    }
+                },
+                "tags" => {
+                    metadata.tags = value
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split(',')
+                        .map(|tag| tag.trim().trim_matches('"').to_string())
+                        .filter(|tag| !tag.is_empty())
+                        .collect()
+                }
+                "cover" => metadata.cover_image = Some(value),
+                "draft" => metadata.draft = matches!(value.as_str(), "true" | "yes"),
+                "layout" => {
+                    // it names a file in `templates/`
+                    let valid = !value.is_empty()
+                        && value
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_'));
+
+                    if valid {
+                        metadata.layout = Some(value);
+                    } else {
+                        let message = format!("Ignored invalid layout `{}` in `{}`.", value, file);
+                        push_message(Type::Warning, &message);
+                    }
+                }
+                _ => {
+                    metadata.extra.insert(key.to_string(), value);
+                }
+            }
+        }
+
+        // the lines after the block keep their numbers
+        let mut content = markdown.to_string();
+        Self::blank_out(&mut content, 0..markdown.len() - rest.len());
+
+        (metadata, content)
+    }
+
+    /// Normalize a `YYYY-MM-DD` or RFC 3339 date to RFC 3339
+    fn parse_date(date: &str) -> Option<String> {
+        if DateTime::parse_from_rfc3339(date).is_ok() {
+            return Some(date.to_string());
+        }
+
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()
+            .map(|date| format!("{}T00:00:00Z", date))
+    }
+
+    /// Find the `rel="next"` URL in a paginated `Link` header
+    pub fn parse_next_link(link_header: &str) -> Option<String> {
+        // <https://api.github.com/user/1/gists?page=2>; rel="next", <...>; rel="last"
+        link_header.split(',').find_map(|link| {
+            let mut segments = link.split(';');
+            let url = segments.next()?.trim();
+
+            if segments.any(|param| param.trim() == "rel=\"next\"") {
+                Some(
+                    url.trim_start_matches('<')
+                        .trim_end_matches('>')
+                        .to_string(),
+                )
+            } else {
+                None
+            }
+        })
+    }
+
+    /// RFC 3339 date-time parsing and sorting (newest first)
+    pub fn sort_by_date(page_map: &HashMap<String, GistPage>) -> Vec<(&String, &GistPage)> {
         /*
-            This is not required atm because gist already outputs a sorted list.
-            It's in descending order so we just traverse the list in reverse.
-            If anyone asks for sorting configurations for their blog listing or
-            the gist fetching goes multi-threaded this will be implemented.
+            The gist listing used to be in order but the downloads are concurrent
+            and the pages live in a `HashMap`, so the order is restored here.
+            Ties are broken by the permalink to keep every build deterministic.
         */
-        unimplemented!();
+        let mut pages: Vec<(&String, &GistPage)> = page_map.iter().collect();
+
+        pages.sort_by(|(a_permalink, a_page), (b_permalink, b_page)| {
+            let a_date = DateTime::parse_from_rfc3339(&a_page.created_at).ok();
+            let b_date = DateTime::parse_from_rfc3339(&b_page.created_at).ok();
+
+            b_date
+                .cmp(&a_date)
+                .then_with(|| a_permalink.cmp(b_permalink))
+        });
+
+        pages
+    }
+
+    /// Sort pages by star count (most starred first), newest first among equals
+    pub fn sort_by_stars(page_map: &HashMap<String, GistPage>) -> Vec<(&String, &GistPage)> {
+        let mut pages = Self::sort_by_date(page_map);
+
+        // stable sort, so the date order is kept within the same star count
+        pages.sort_by_key(|(_, page)| Reverse(page.star_count));
+
+        pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn options() -> RenderOptions {
+        Config::default().render_options()
+    }
+
+    fn math_options() -> RenderOptions {
+        RenderOptions {
+            math: true,
+            ..options()
+        }
+    }
+
+    fn attachment(file: &str, content: &str) -> Attachment {
+        Attachment {
+            file: file.to_string(),
+            content: content.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn finds_the_next_page_between_other_links() {
+        let link = "<https://api.github.com/user/1/gists?page=1>; rel=\"prev\", \
+                    <https://api.github.com/user/1/gists?page=3>; rel=\"next\", \
+                    <https://api.github.com/user/1/gists?page=9>; rel=\"last\"";
+
+        assert_eq!(
+            ParserUtils::parse_next_link(link).as_deref(),
+            Some("https://api.github.com/user/1/gists?page=3")
+        );
+    }
+
+    #[test]
+    fn has_no_next_page_on_the_last_page() {
+        let link = "<https://api.github.com/user/1/gists?page=1>; rel=\"first\", \
+                    <https://api.github.com/user/1/gists?page=8>; rel=\"prev\"";
+
+        assert_eq!(ParserUtils::parse_next_link(link), None);
+        assert_eq!(ParserUtils::parse_next_link(""), None);
+    }
+
+    #[test]
+    fn ignores_whitespace_in_link_headers() {
+        let link = "  <https://api.github.com/user/1/gists?page=2>  ;  rel=\"next\"  ,\n\t\
+                    <https://api.github.com/user/1/gists?page=5> ; rel=\"last\"";
+
+        assert_eq!(
+            ParserUtils::parse_next_link(link).as_deref(),
+            Some("https://api.github.com/user/1/gists?page=2")
+        );
+    }
+
+    #[test]
+    fn parses_the_revision_marker() {
+        let markdown = "# Title\n\n<!-- gisture:revision 3f1a9c0de -->\n";
+
+        assert_eq!(
+            ParserUtils::parse_revision_marker(markdown).as_deref(),
+            Some("3f1a9c0de")
+        );
+        assert_eq!(ParserUtils::parse_revision_marker("# Title\n"), None);
+    }
+
+    #[test]
+    fn ignores_revision_markers_that_arent_hex() {
+        let markdown = "<!-- gisture:revision main -->\n";
+        assert_eq!(ParserUtils::parse_revision_marker(markdown), None);
+
+        let markdown = "<!-- gisture:revision  -->\n";
+        assert_eq!(ParserUtils::parse_revision_marker(markdown), None);
+    }
+
+    #[test]
+    fn ignores_revision_markers_in_code() {
+        let markdown = "Pin with `<!-- gisture:revision abc123 -->` or:\n\n\
+                        ```html\n<!-- gisture:revision def456 -->\n```\n";
+        assert_eq!(ParserUtils::parse_revision_marker(markdown), None);
+
+        let markdown = format!("{}\n<!-- gisture:revision 789abc -->\n", markdown);
+        assert_eq!(
+            ParserUtils::parse_revision_marker(&markdown).as_deref(),
+            Some("789abc")
+        );
+    }
+
+    #[test]
+    fn renders_diffs_with_line_counts() {
+        let (html, additions, deletions) =
+            ParserUtils::render_diff("# Old\nsame\n", "# New <b>\n& more\nsame\n");
+
+        assert_eq!(
+            html,
+            "<pre class=\"diff\"><del>- # Old</del>\n\
+             <ins>+ # New &lt;b&gt;</ins>\n\
+             <ins>+ &amp; more</ins>\n  same\n</pre>"
+        );
+        assert_eq!((additions, deletions), (2, 1));
+    }
+
+    #[test]
+    fn separates_distant_changes_in_diffs() {
+        let old: String = (1..=12).map(|line| format!("{}\n", line)).collect();
+        let new = old
+            .replace("1\n2\n", "one\n2\n")
+            .replace("12\n", "twelve\n");
+
+        let (html, additions, deletions) = ParserUtils::render_diff(&old, &new);
+
+        assert_eq!(
+            html,
+            "<pre class=\"diff\"><del>- 1</del>\n<ins>+ one</ins>\n  2\n  3\n  4\n\
+             <span class=\"diff-hunk\">…</span>\n  9\n  10\n  11\n\
+             <del>- 12</del>\n<ins>+ twelve</ins>\n</pre>"
+        );
+        assert_eq!((additions, deletions), (2, 2));
+    }
+
+    #[test]
+    fn removes_styles_and_scripts_from_comments() {
+        let html = ParserUtils::parse_untrusted_markdown_to_html(
+            String::from(
+                "Hi <span style=\"position:fixed;inset:0\" class=\"syn-code\">there</span>\
+             <script>alert(1)</script>\n",
+            ),
+            &options(),
+        );
+
+        assert_eq!(html, "<p>Hi <span>there</span></p>\n");
+    }
+
+    #[test]
+    fn keeps_the_highlighting_of_code_in_comments() {
+        let html = ParserUtils::parse_untrusted_markdown_to_html(
+            String::from("Try \u{fffc}0\u{fffc}:\n\n```\n<b>\n```\n"),
+            &options(),
+        );
+
+        assert_eq!(
+            html,
+            "<p>Try 0:</p>\n\
+             <pre style=\"background-color:#ffffff;\"><code>\
+             <span style=\"color:#323232;\">&lt;b&gt;\n</span></code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn percent_encodes_attachment_urls() {
+        assert_eq!(
+            ParserUtils::attachment_url("post", "main.rs"),
+            "/post/main.rs"
+        );
+        assert_eq!(
+            ParserUtils::attachment_url("post", "my notes #1?.txt"),
+            "/post/my%20notes%20%231%3F.txt"
+        );
+        assert_eq!(
+            ParserUtils::attachment_url("café", "ü.csv"),
+            "/caf%C3%A9/%C3%BC.csv"
+        );
+    }
+
+    #[test]
+    fn embeds_attachments_as_fenced_code() {
+        let attachments = [attachment("main.rs", "fn main() {}\n")];
+        let expanded = ParserUtils::expand_embeds(
+            "Intro\n\n{{embed \"main.rs\"}}\n\nOutro\n",
+            &attachments,
+            "post.blog.md",
+            &options(),
+        );
+
+        assert_eq!(expanded, "Intro\n\n```rs\nfn main() {}\n```\n\n\nOutro\n");
+    }
+
+    #[test]
+    fn leaves_directives_in_code_alone() {
+        let markdown = "Use `{{embed \"x.rs\"}}` like so.\n\n```\n{{embed \"x.rs\"}}\n```\n";
+        let attachments = [attachment("x.rs", "let x = 1;")];
+
+        assert_eq!(
+            ParserUtils::expand_embeds(markdown, &attachments, "post.blog.md", &options()),
+            markdown
+        );
+    }
+
+    #[test]
+    fn leaves_unresolved_directives_in_the_text() {
+        let markdown = "See {{embed \"missing.rs\"}} and {{embed \"image.png\"}}.\n";
+        let attachments = [Attachment {
+            file: String::from("image.png"),
+            content: vec![0x89, 0x50, 0xff, 0xfe],
+        }];
+
+        assert_eq!(
+            ParserUtils::expand_embeds(markdown, &attachments, "post.blog.md", &options()),
+            markdown
+        );
+    }
+
+    #[test]
+    fn indents_embeds_inside_list_items() {
+        let attachments = [attachment("a.py", "x = 1\n\ny = 2\n")];
+        let expanded = ParserUtils::expand_embeds(
+            "1. Run {{embed \"a.py\"}}\n2. Done\n",
+            &attachments,
+            "post.blog.md",
+            &options(),
+        );
+
+        assert_eq!(
+            expanded,
+            "1. Run\n   ```py\n   x = 1\n\n   y = 2\n   ```\n\n2. Done\n"
+        );
+
+        // the code stays inside the first item
+        let html = ParserUtils::parse_markdown_to_html(expanded, &options());
+        assert_eq!(html.matches("<li>").count(), 2);
+        assert!(html.find("<pre").unwrap() < html.find("Done").unwrap());
+    }
+
+    #[test]
+    fn embeds_datasets_as_tables() {
+        let attachments = [attachment("data.csv", "name,score\nada,3\n")];
+        let expanded = ParserUtils::expand_embeds(
+            "{{embed \"data.csv\"}}\n",
+            &attachments,
+            "post.blog.md",
+            &options(),
+        );
+
+        assert!(expanded.starts_with("<table class=\"csv-table\">"));
+        assert!(expanded.contains("<td>ada</td>"));
+    }
+
+    #[test]
+    fn parses_the_metadata_block() {
+        let markdown =
+            "<!-- gisture\ntitle: Custom: Title\ndescription: About it\nslug: hello-world\n\
+                        date: 2020-05-17\ntags: [rust, \"web dev\"]\ncover: /cover.png\n\
+                        layout: wide\nmood: happy\n-->\n\n# Heading\n";
+        let (metadata, content) = ParserUtils::parse_metadata_block(markdown, "post.blog.md");
+
+        assert_eq!(metadata.title.as_deref(), Some("Custom: Title"));
+        assert_eq!(metadata.description.as_deref(), Some("About it"));
+        assert_eq!(metadata.slug.as_deref(), Some("hello-world"));
+        assert_eq!(metadata.date.as_deref(), Some("2020-05-17T00:00:00Z"));
+        assert_eq!(metadata.tags, ["rust", "web dev"]);
+        assert_eq!(metadata.cover_image.as_deref(), Some("/cover.png"));
+        assert_eq!(metadata.layout.as_deref(), Some("wide"));
+        assert_eq!(metadata.extra["mood"], "happy");
+        assert!(!metadata.draft);
+
+        // the block is blanked out, the lines after it keep their numbers
+        assert_eq!(content, format!("{}\n\n# Heading\n", "\n".repeat(9)));
+    }
+
+    #[test]
+    fn ignores_invalid_metadata_values() {
+        let markdown =
+            "<!-- gisture\nslug: index.html\nlayout: ../page\ndate: yesterday\ndraft: yes\n-->\n";
+        let (metadata, _) = ParserUtils::parse_metadata_block(markdown, "post.blog.md");
+
+        assert_eq!(metadata.slug, None);
+        assert_eq!(metadata.layout, None);
+        assert_eq!(metadata.date, None);
+        assert!(metadata.draft);
+
+        for slug in ["../etc", ".hidden", "sitemap.xml", "a/b", ""] {
+            let markdown = format!("<!-- gisture\nslug: {}\n-->\n", slug);
+            let (metadata, _) = ParserUtils::parse_metadata_block(&markdown, "post.blog.md");
+            assert_eq!(metadata.slug, None, "slug `{}`", slug);
+        }
+    }
+
+    #[test]
+    fn leaves_markdown_without_a_metadata_block_alone() {
+        for markdown in [
+            "# Title\n",
+            "<!-- gisture -->\n# Title\n",
+            "<!-- gisture\ntitle: x\n",
+        ] {
+            let (metadata, content) = ParserUtils::parse_metadata_block(markdown, "post.blog.md");
+
+            assert_eq!(metadata.title, None);
+            assert_eq!(content, markdown);
+        }
+    }
+
+    #[test]
+    fn extracts_the_title_and_its_range() {
+        let markdown = "Intro\n\n# The *Big* `Title`\n\nText\n";
+
+        assert_eq!(
+            ParserUtils::extract_title(markdown),
+            Some((String::from("The Big Title"), 7..27))
+        );
+    }
+
+    #[test]
+    fn extracts_setext_titles() {
+        let markdown = "Multi\nline\n===\n\n# Later\n";
+
+        assert_eq!(
+            ParserUtils::extract_title(markdown),
+            Some((String::from("Multi line"), 0..15))
+        );
+    }
+
+    #[test]
+    fn only_takes_level_1_headings_outside_code_as_titles() {
+        let markdown = "## Subtitle\n\n```sh\n# comment\n```\n\n    # indented\n";
+
+        assert_eq!(ParserUtils::extract_title(markdown), None);
+    }
+
+    #[test]
+    fn highlights_and_escapes_code_blocks() {
+        let fence = FenceInfo {
+            lang: String::from("txt"),
+            ..FenceInfo::default()
+        };
+
+        assert_eq!(
+            ParserUtils::highlight_code_block("a < b\n", &fence, &options()),
+            "<pre style=\"background-color:#ffffff;\"><code class=\"language-txt\">\
+             <span style=\"color:#323232;\">a &lt; b\n</span></code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn reopens_scopes_on_every_classed_line() {
+        let syntax = SYNTAX_SET.find_syntax_by_token("rs").unwrap();
+        let lines = ParserUtils::classed_lines("/* a\nb */\n", syntax);
+
+        let comment = "<span class=\"syn-source syn-rust\">\
+                       <span class=\"syn-comment syn-block syn-rust\">";
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(comment) && lines[0].ends_with("</span></span>"));
+        assert!(lines[1].starts_with(&format!("{}b ", comment)));
+    }
+
+    #[test]
+    fn highlights_code_blocks_but_not_inline_code() {
+        let html = ParserUtils::parse_markdown_to_html(
+            String::from("`x < y`\n\n```\nx\n```\n"),
+            &options(),
+        );
+
+        assert_eq!(
+            html,
+            "<p><code>x &lt; y</code></p>\n\
+             <pre style=\"background-color:#ffffff;\"><code>\
+             <span style=\"color:#323232;\">x\n</span></code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_classed_lines() {
+        let syntax = SYNTAX_SET.find_syntax_plain_text();
+
+        assert_eq!(
+            ParserUtils::classed_lines("a < b\n", syntax),
+            ["<span class=\"syn-text syn-plain\">a &lt; b\n</span>"]
+        );
+    }
+
+    #[test]
+    fn has_no_stylesheet_for_inline_highlighting() {
+        assert_eq!(ParserUtils::syntax_css(&options()), None);
+    }
+
+    #[test]
+    fn generates_a_stylesheet_for_class_highlighting() {
+        let options = RenderOptions {
+            class_highlighting: true,
+            syntax_dark_theme: ParserUtils::theme("syntax_dark_theme", "base16-ocean.dark").ok(),
+            ..options()
+        };
+
+        let css = ParserUtils::syntax_css(&options).unwrap();
+        assert!(css.contains(".syn-code"));
+        assert!(css.contains("@media (prefers-color-scheme: dark)"));
+    }
+
+    #[test]
+    fn finds_the_default_themes() {
+        assert_eq!(options().syntax_theme.name.as_deref(), Some("GitHub"));
+
+        let theme = ParserUtils::theme("syntax_dark_theme", "base16-ocean.dark").unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Base16 Ocean Dark"));
+    }
+
+    #[test]
+    fn lists_the_available_themes_for_unknown_ones() {
+        let error = ParserUtils::theme("syntax_theme", "InspiredGithub").unwrap_err();
+
+        assert!(error.starts_with("Unknown `syntax_theme` \"InspiredGithub\" (available: "));
+        assert!(error.contains("InspiredGitHub, "));
+    }
+
+    #[test]
+    fn parses_fence_info() {
+        let fence = ParserUtils::parse_fence_info("rust,ignore {linenos, hl=1,3-5 hl=8}");
+
+        assert_eq!(fence.lang, "rust");
+        assert!(fence.line_numbers);
+        assert_eq!(fence.highlighted_lines, [1..=1, 3..=5, 8..=8]);
+
+        let fence = ParserUtils::parse_fence_info("py");
+        assert_eq!(fence.lang, "py");
+        assert!(!fence.line_numbers && fence.highlighted_lines.is_empty());
+    }
+
+    #[test]
+    fn ignores_invalid_fence_attributes() {
+        let fence = ParserUtils::parse_fence_info("{wrap, hl=0 5-3 x 2, linenos 4}");
+
+        assert_eq!(fence.lang, "");
+        assert!(fence.line_numbers);
+        // `4` comes after `linenos`, so it isn't part of `hl` anymore
+        assert_eq!(fence.highlighted_lines, [2..=2]);
+    }
+
+    #[test]
+    fn parses_line_ranges() {
+        assert_eq!(ParserUtils::parse_line_range("3"), Some(3..=3));
+        assert_eq!(ParserUtils::parse_line_range("3-5"), Some(3..=5));
+        assert_eq!(ParserUtils::parse_line_range("0"), None);
+        assert_eq!(ParserUtils::parse_line_range("5-3"), None);
+        assert_eq!(ParserUtils::parse_line_range("3-"), None);
+        assert_eq!(ParserUtils::parse_line_range("x"), None);
+    }
+
+    #[test]
+    fn numbers_and_marks_lines() {
+        let fence = ParserUtils::parse_fence_info("{linenos, hl=2}");
+        let html = ParserUtils::highlight_code_block("a\nb\n", &fence, &options());
+
+        assert_eq!(
+            html,
+            "<pre style=\"background-color:#ffffff;\"><code>\
+             <span class=\"syn-code-line\"><span class=\"syn-code-lineno\">1</span>\
+             <span style=\"color:#323232;\">a\n</span></span>\
+             <span class=\"syn-code-line syn-code-hl\"><span class=\"syn-code-lineno\">2</span>\
+             <span style=\"color:#323232;\">b\n</span></span></code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn separates_words_at_block_tags_only() {
+        let html = "<h2>Setup</h2>\n<p>Run <code>make</code> <em>twice</em>, here.</p>\n\
+                    <table><tr><td>a</td><td>b</td></tr></table>";
+
+        assert_eq!(
+            ParserUtils::plain_text(html),
+            "Setup Run make twice, here. a b"
+        );
+    }
+
+    #[test]
+    fn decodes_entities_in_plain_text() {
+        assert_eq!(
+            ParserUtils::plain_text("<p>Fish &amp; chips &lt;3</p>"),
+            "Fish & chips <3"
+        );
+    }
+
+    #[test]
+    fn counts_words_but_not_punctuation() {
+        assert_eq!(
+            ParserUtils::word_count("<p>One, two &mdash; three!</p><ul><li>four</li></ul>"),
+            4
+        );
+        assert_eq!(ParserUtils::word_count(""), 0);
+    }
+
+    #[test]
+    fn excerpts_the_first_paragraph() {
+        let html = "<h1>Title</h1>\n<p></p>\n<p>First <em>one</em>.</p>\n<p>Second.</p>\n";
+
+        assert_eq!(ParserUtils::excerpt(html), "First one.");
+        assert_eq!(ParserUtils::excerpt("<h1>Title</h1>\n"), "");
+    }
+
+    #[test]
+    fn excerpts_the_paragraphs_before_the_more_marker() {
+        let html = "<p>First.</p>\n<p>Second.</p>\n<!-- more -->\n<p>Third.</p>\n";
+        assert_eq!(ParserUtils::excerpt(html), "First. Second.");
+
+        let html = "<p>First.</p>\n<p>Second<!--more--> and third.</p>\n";
+        assert_eq!(ParserUtils::excerpt(html), "First. Second");
+    }
+
+    #[test]
+    fn blanks_out_markdown_but_keeps_line_breaks() {
+        let mut markdown = String::from("# Title\n\nText\n");
+        ParserUtils::blank_out(&mut markdown, 0..9);
+
+        assert_eq!(markdown, "\n\nText\n");
+    }
+
+    #[test]
+    fn renders_inline_and_display_math() {
+        let rendered = ParserUtils::render_math(
+            "Energy $E = mc^2$.\n\n$$x$$\n",
+            "`post.blog.md`",
+            &math_options(),
+        );
+
+        assert_eq!(
+            rendered,
+            format!(
+                "Energy {}.\n\n{}\n",
+                Math::to_mathml("E = mc^2", false).unwrap(),
+                Math::to_mathml("x", true).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn leaves_dollars_alone_unless_math_is_enabled() {
+        let markdown = "Energy $E = mc^2$.\n";
+
+        assert_eq!(
+            ParserUtils::render_math(markdown, "`post.blog.md`", &options()),
+            markdown
+        );
+    }
+
+    #[test]
+    fn leaves_currency_alone() {
+        let markdown = "It costs $5 and $10, or $ 20 $.\n";
+
+        assert_eq!(
+            ParserUtils::render_math(markdown, "`post.blog.md`", &math_options()),
+            markdown
+        );
+    }
+
+    #[test]
+    fn leaves_math_in_code_and_escaped_dollars_alone() {
+        let markdown = "Run `echo $HOME$` or \\$x\\$.\n\n```sh\necho $x$\n```\n";
+
+        assert_eq!(
+            ParserUtils::render_math(markdown, "`post.blog.md`", &math_options()),
+            markdown
+        );
+    }
+
+    #[test]
+    fn reads_math_as_a_single_word() {
+        let markdown = ParserUtils::render_math(
+            "Energy $E = mc^2$ is famous.\n",
+            "`post.blog.md`",
+            &math_options(),
+        );
+        let html = ParserUtils::parse_markdown_to_html(markdown, &options());
+
+        assert_eq!(ParserUtils::plain_text(&html), "Energy E=mc2 is famous.");
+        assert_eq!(ParserUtils::word_count(&html), 4);
     }
 }
@@ -0,0 +1,588 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::SystemTime;
+
+use crate::{
+    config::RenderOptions,
+    gist::{Attachment, Comment, GistPage, PageMetadata, Revision},
+    messages::{push_message, Type},
+    notebook::Notebook,
+    parsers::ParserUtils,
+    table::Table,
+    toc::Toc,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+// gisture files should end with this, to distinguish them from other files
+pub static BLOG_EXTENSION: &str = ".blog.md";
+
+// Jupyter notebooks are published as blogs too
+pub static NOTEBOOK_EXTENSION: &str = ".blog.ipynb";
+
+// so are CSV/TSV datasets, as a table
+pub static CSV_EXTENSION: &str = ".blog.csv";
+pub static TSV_EXTENSION: &str = ".blog.tsv";
+
+/// Somewhere to read the blog entries from (gists or a local directory)
+pub(crate) trait ContentSource {
+    /// Organize a blog map of all entries in the source
+    async fn get_all_blogs(&self) -> HashMap<String, GistPage>;
+}
+
+/// A `*.blog.md` (or notebook/dataset) file along with the metadata of where it came from
+pub(crate) struct BlogEntry {
+    pub file: String,
+    // the notebook's JSON or the dataset for `*.blog.ipynb` and `*.blog.csv` files
+    pub markdown: String,
+    pub description: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub html_url: String,
+    pub revision: Option<String>,
+    pub revisions: Vec<Revision>,
+    pub comments: Vec<Comment>,
+    pub star_count: u64,
+    pub fork_count: u64,
+    pub attachments: Vec<Attachment>,
+}
+
+impl BlogEntry {
+    /// Every kind of gisture file
+    fn blog_extensions() -> [&'static str; 4] {
+        [
+            BLOG_EXTENSION,
+            NOTEBOOK_EXTENSION,
+            CSV_EXTENSION,
+            TSV_EXTENSION,
+        ]
+    }
+
+    /// Whether a file is a gisture file (`*.blog.md`, `*.blog.ipynb`, `*.blog.csv` or `*.blog.tsv`)
+    pub fn is_blog_file(file: &str) -> bool {
+        Self::blog_extensions()
+            .iter()
+            .any(|extension| file.ends_with(extension))
+    }
+
+    /// Filename patterns of every kind of gisture file, for messages
+    pub fn blog_patterns() -> String {
+        let patterns: Vec<String> = Self::blog_extensions()
+            .iter()
+            .map(|extension| format!("*{}", extension))
+            .collect();
+        patterns.join(", ")
+    }
+
+    /// Permalink of a gisture file, the filename without the gisture extension
+    pub fn permalink(file: &str) -> String {
+        Self::blog_extensions()
+            .iter()
+            .find_map(|extension| file.strip_suffix(extension))
+            .unwrap_or(file)
+            .to_string()
+    }
+
+    /// Parse the entry into a page and its permalink (`None` if it isn't a valid blog)
+    pub fn into_page(mut self, options: &RenderOptions) -> Option<(String, GistPage)> {
+        // editors on Windows like to start files with a byte order mark
+        if let Some(markdown) = self.markdown.strip_prefix('\u{feff}') {
+            self.markdown = markdown.to_string();
+        }
+
+        // the metadata block has to go before the title is looked for
+        let metadata = if self.file.ends_with(BLOG_EXTENSION) {
+            let (metadata, markdown) =
+                ParserUtils::parse_metadata_block(&self.markdown, &self.file);
+            self.markdown = markdown;
+            metadata
+        } else {
+            PageMetadata::default()
+        };
+
+        if metadata.draft {
+            let message = format!("`{}` is a draft. (SKIPPED)", self.file);
+            push_message(Type::Info, &message);
+            return None;
+        }
+
+        // permalink is the filename without the gisture extension (unless there's a slug)
+        let permalink = metadata
+            .slug
+            .clone()
+            .unwrap_or_else(|| Self::permalink(&self.file));
+
+        // only gisture files end up here, so a CSV/TSV file is a `*.blog.csv` or `*.blog.tsv`
+        let mut toc = Toc::new(options.heading_anchors);
+
+        let (page_title, html_content) = match Table::delimiter(&self.file) {
+            Some(delimiter) => {
+                // datasets have no heading, so they're titled by the gist's description
+                let title = match self.description.trim() {
+                    "" | "null" => permalink.clone(),
+                    description => description.to_string(),
+                };
+                let html = Table::to_html(&self.markdown, delimiter, &self.file, options);
+                (title, html)
+            }
+            None => self.parse_title_and_content(metadata.title.clone(), options, &mut toc)?,
+        };
+
+        let word_count = ParserUtils::word_count(&html_content);
+        // rounded up, a short blog is still a minute's read
+        let reading_time = word_count.div_ceil(options.words_per_minute).max(1);
+        let excerpt = ParserUtils::excerpt(&html_content);
+
+        // gists without a description have a `null` one
+        let description = match metadata.description.unwrap_or(self.description) {
+            description if matches!(description.trim(), "" | "null") => excerpt.clone(),
+            description => description,
+        };
+
+        let page_data: GistPage = GistPage {
+            title: page_title,
+            description,
+            created_at: metadata.date.unwrap_or(self.created_at),
+            updated_at: self.updated_at,
+            html_url: self.html_url,
+            content: html_content,
+            toc: toc.to_html(),
+            word_count,
+            reading_time,
+            excerpt,
+            revision: self.revision,
+            revisions: self.revisions,
+            comments: self.comments,
+            star_count: self.star_count,
+            fork_count: self.fork_count,
+            attachments: self.attachments,
+            tags: metadata.tags,
+            cover_image: metadata.cover_image,
+            layout: metadata.layout,
+            extra: metadata.extra,
+        };
+
+        Some((permalink, page_data))
+    }
+
+    /// Parse the title and HTML content of a markdown blog or a notebook
+    /// (a title from the metadata block makes the heading optional)
+    fn parse_title_and_content(
+        &self,
+        title: Option<String>,
+        options: &RenderOptions,
+        toc: &mut Toc,
+    ) -> Option<(String, String)> {
+        if self.markdown.trim().is_empty() {
+            let message = format!("Encountered empty blog: `{}` (SKIPPED)", self.file);
+            push_message(Type::Warning, &message);
+            return None;
+        }
+
+        let notebook = if self.file.ends_with(NOTEBOOK_EXTENSION) {
+            match Notebook::parse(&self.markdown) {
+                Ok(notebook) => Some(notebook),
+                Err(error) => {
+                    let message = format!(
+                        "`{}` isn't a valid Jupyter notebook: \n\t{} (SKIPPED)",
+                        self.file, error
+                    );
+                    push_message(Type::Warning, &message);
+                    return None;
+                }
+            }
+        } else {
+            None
+        };
+
+        // a title from the metadata block leaves the heading alone
+        let strip_title = title.is_none() && options.strip_title;
+
+        let heading = match &notebook {
+            Some(notebook) => notebook.title(),
+            None => ParserUtils::extract_title(&self.markdown),
+        };
+
+        // the first level 1 heading (`# Title` or `Title\n===`) is the blog's title
+        let page_title = match (title, &heading) {
+            (Some(title), _) => title,
+            (None, Some((title, _))) => title.to_string(),
+            (None, None) => {
+                let message = format!(
+                    "`{}` doesn't contain a title (`# Title`). (SKIPPED)",
+                    self.file
+                );
+                push_message(Type::Warning, &message);
+                return None;
+            }
+        };
+
+        let html_content = match notebook {
+            Some(notebook) => {
+                notebook.to_html(&self.attachments, &self.file, strip_title, options, toc)
+            }
+            None => {
+                let mut markdown = self.markdown.clone();
+
+                // so the title isn't rendered twice by templates which show it on their own
+                if let (true, Some((_, range))) = (strip_title, heading) {
+                    ParserUtils::blank_out(&mut markdown, range);
+                }
+
+                // before the embeds, so the lines of the math warnings are the file's own
+                let location = format!("`{}`", self.file);
+                let markdown = ParserUtils::render_math(&markdown, &location, options);
+
+                // inline the `{{embed "file"}}` directives before the markdown is parsed
+                let markdown =
+                    ParserUtils::expand_embeds(&markdown, &self.attachments, &self.file, options);
+
+                // convert Markdown to HTML
+                ParserUtils::parse_markdown_with_toc(&markdown, toc, options)
+            }
+        };
+
+        Some((page_title, html_content))
+    }
+}
+
+/// A local directory of `*.blog.md` files (drafts, or blogs that never go to GitHub)
+pub(crate) struct LocalSource {
+    pub content_dir: String,
+    pub options: RenderOptions,
+}
+
+impl LocalSource {
+    /// RFC 3339 timestamp of a file's creation/modification time
+    fn timestamp(time: SystemTime) -> String {
+        DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+
+    /// Files directly inside a directory along with their names,
+    /// sorted by filename so builds are deterministic
+    fn list_files(dir: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+        let mut files: Vec<(PathBuf, String)> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                (
+                    entry.path(),
+                    entry.file_name().to_string_lossy().to_string(),
+                )
+            })
+            .filter(|(path, _)| path.is_file())
+            .collect();
+        files.sort_by(|(_, a), (_, b)| a.cmp(b));
+
+        Ok(files)
+    }
+
+    /// Read the attachments of a blog, the files of the directory named after the blog file
+    /// (`hello/` for `hello.blog.md`, like the other files of a gist)
+    fn read_attachments(attachment_dir: &Path) -> Vec<Attachment> {
+        // most blogs have no attachments at all
+        let files = match Self::list_files(attachment_dir) {
+            Ok(files) => files,
+            Err(_) => return Vec::new(),
+        };
+
+        files
+            .into_iter()
+            .filter(|(_, file)| !BlogEntry::is_blog_file(file))
+            .filter_map(|(path, file)| match fs::read(&path) {
+                Ok(content) => Some(Attachment { file, content }),
+                Err(error) => {
+                    let message = format!(
+                        "Failed to read attachment `{}` due to: \n\t{} (SKIPPED)",
+                        path.display(),
+                        error
+                    );
+                    push_message(Type::Warning, &message);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Read a single gisture file into a blog entry
+    fn read_entry(path: PathBuf, file: String, attachments: Vec<Attachment>) -> BlogEntry {
+        let markdown = match fs::read_to_string(&path) {
+            Ok(markdown) => markdown,
+            Err(error) => {
+                let message = format!("Failed to read `{}` due to: \n\t{}", path.display(), error);
+                push_message(Type::Error, &message);
+                exit(1)
+            }
+        };
+
+        let (created_at, updated_at) = match fs::metadata(&path) {
+            Ok(metadata) => {
+                let modified = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+                // not every filesystem keeps track of creation times
+                let created = metadata.created().unwrap_or(modified);
+                (Self::timestamp(created), Self::timestamp(modified))
+            }
+            Err(_) => {
+                let now = Self::timestamp(SystemTime::now());
+                (now.clone(), now)
+            }
+        };
+
+        BlogEntry {
+            file,
+            markdown,
+            description: String::new(),
+            created_at,
+            updated_at,
+            html_url: String::new(),
+            revision: None,
+            revisions: Vec::new(),
+            comments: Vec::new(),
+            star_count: 0,
+            fork_count: 0,
+            attachments,
+        }
+    }
+}
+
+impl ContentSource for LocalSource {
+    async fn get_all_blogs(&self) -> HashMap<String, GistPage> {
+        let message = format!("Reading local blogs from `{}`.", self.content_dir);
+        push_message(Type::Info, &message);
+
+        let content_dir = Path::new(&self.content_dir);
+
+        let files = match Self::list_files(content_dir) {
+            Ok(files) => files,
+            Err(error) => {
+                let message = format!(
+                    "Failed to read the content directory `{}` due to: \n\t{}",
+                    self.content_dir, error
+                );
+                push_message(Type::Error, &message);
+                exit(1)
+            }
+        };
+
+        // to store all blog's permalink and their contents
+        let mut blogs: HashMap<String, GistPage> = HashMap::new();
+
+        for (path, file) in files {
+            if !BlogEntry::is_blog_file(&file) {
+                continue;
+            }
+
+            let attachments =
+                Self::read_attachments(&content_dir.join(BlogEntry::permalink(&file)));
+
+            if let Some((permalink, page_data)) =
+                Self::read_entry(path, file.clone(), attachments).into_page(&self.options)
+            {
+                // a slug may take another blog's permalink
+                match blogs.entry(permalink) {
+                    Entry::Occupied(entry) => {
+                        let message = format!(
+                            "`{}` has the same permalink as another blog (`{}`). (SKIPPED)",
+                            file,
+                            entry.key()
+                        );
+                        push_message(Type::Warning, &message);
+                        continue;
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(page_data);
+                    }
+                }
+
+                let message = format!("Read blog \"{}\".", file);
+                push_message(Type::Info, &message);
+            }
+        }
+
+        if blogs.is_empty() {
+            let message = format!(
+                "0 gisture blogs ({}) found in `{}`",
+                BlogEntry::blog_patterns(),
+                self.content_dir
+            );
+            push_message(Type::Warning, &message);
+            exit(0)
+        }
+
+        blogs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::config::Config;
+
+    fn options() -> RenderOptions {
+        Config::default().render_options()
+    }
+
+    /// A content directory with the given files, removed when dropped
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("gisture-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            for (file, content) in files {
+                let path = dir.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            }
+
+            Self(dir)
+        }
+
+        fn blogs(&self) -> HashMap<String, GistPage> {
+            let content_dir = self.0.to_string_lossy().to_string();
+            block_on(
+                LocalSource {
+                    content_dir,
+                    options: options(),
+                }
+                .get_all_blogs(),
+            )
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn entry(file: &str, markdown: &str) -> BlogEntry {
+        BlogEntry {
+            file: file.to_string(),
+            markdown: markdown.to_string(),
+            description: String::new(),
+            created_at: String::new(),
+            updated_at: String::new(),
+            html_url: String::new(),
+            revision: None,
+            revisions: Vec::new(),
+            comments: Vec::new(),
+            star_count: 0,
+            fork_count: 0,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reads_a_content_directory() {
+        let fixture = Fixture::new(
+            "content",
+            &[
+                (
+                    "hello.blog.md",
+                    "<!-- gisture\nslug: hi\ntags: [rust]\n-->\n\
+                     # Hello\n\nFirst words here.\n\n{{embed \"main.py\"}}\n",
+                ),
+                ("draft.blog.md", "<!-- gisture\ndraft: true\n-->\n# Draft\n"),
+                ("numbers.blog.csv", "n,square\n2,4\n"),
+                ("hello/main.py", "print('hi')\n"),
+                ("hello/notes.txt", "not a blog\n"),
+                ("numbers/plot.png", "not text"),
+                ("stray.txt", "nobody's attachment\n"),
+            ],
+        );
+
+        let blogs = fixture.blogs();
+
+        let mut permalinks: Vec<&str> = blogs.keys().map(String::as_str).collect();
+        permalinks.sort_unstable();
+        assert_eq!(permalinks, ["hi", "numbers"]);
+
+        let hello = &blogs["hi"];
+        assert_eq!(hello.title, "Hello");
+        assert_eq!(hello.tags, ["rust"]);
+        assert_eq!(hello.excerpt, "First words here.");
+        // without a description, the excerpt describes the blog
+        assert_eq!(hello.description, "First words here.");
+        assert!(hello.content.contains("print"));
+        // the files of the directory named after the blog are its attachments
+        let attachments: Vec<&str> = hello.attachments.iter().map(|a| a.file.as_str()).collect();
+        assert_eq!(attachments, ["main.py", "notes.txt"]);
+
+        let numbers = &blogs["numbers"];
+        assert_eq!(numbers.title, "numbers");
+        assert!(numbers.content.contains(">square</th>"));
+        let attachments: Vec<&str> = numbers
+            .attachments
+            .iter()
+            .map(|a| a.file.as_str())
+            .collect();
+        assert_eq!(attachments, ["plot.png"]);
+    }
+
+    #[test]
+    fn skips_blogs_with_a_taken_permalink() {
+        let fixture = Fixture::new(
+            "permalinks",
+            &[
+                ("a.blog.md", "<!-- gisture\nslug: b\n-->\n# A\n"),
+                ("b.blog.md", "# B\n"),
+            ],
+        );
+
+        let blogs = fixture.blogs();
+
+        assert_eq!(blogs.len(), 1);
+        assert_eq!(blogs["b"].title, "A");
+    }
+
+    #[test]
+    fn lists_every_kind_of_gisture_file() {
+        assert_eq!(
+            BlogEntry::blog_patterns(),
+            "*.blog.md, *.blog.ipynb, *.blog.csv, *.blog.tsv"
+        );
+    }
+
+    #[test]
+    fn counts_words_and_reading_time() {
+        let words = vec!["word"; 450].join(" ");
+        let (_, page) = entry("long.blog.md", &format!("# Long\n\n{}\n", words))
+            .into_page(&options())
+            .unwrap();
+
+        // the title is a word too
+        assert_eq!(page.word_count, 451);
+        assert_eq!(page.reading_time, 3);
+    }
+
+    #[test]
+    fn skips_blogs_without_a_title() {
+        assert!(entry("untitled.blog.md", "Just text.\n")
+            .into_page(&options())
+            .is_none());
+        assert!(entry("empty.blog.md", "\u{feff}  \n")
+            .into_page(&options())
+            .is_none());
+    }
+
+    #[test]
+    fn titles_blogs_from_the_metadata_block() {
+        let (permalink, page) = entry(
+            "post.blog.md",
+            "<!-- gisture\ntitle: Custom\ndescription: About\n-->\nNo heading.\n",
+        )
+        .into_page(&options())
+        .unwrap();
+
+        assert_eq!(permalink, "post");
+        assert_eq!(page.title, "Custom");
+        assert_eq!(page.description, "About");
+        assert_eq!(page.content, "<p>No heading.</p>\n");
+    }
+}
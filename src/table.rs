@@ -0,0 +1,299 @@
+use std::process::exit;
+
+use csv::ReaderBuilder;
+use html_escape::encode_text;
+
+use crate::{
+    config::RenderOptions,
+    messages::{push_message, Type},
+};
+
+/// Whether the first row of a table is its header (`csv_header`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CsvHeader {
+    Auto,
+    Always,
+    Never,
+}
+
+/// CSV/TSV files rendered as HTML tables
+pub(crate) struct Table;
+
+impl Table {
+    /// Delimiter of a CSV/TSV file (`None` for any other file)
+    pub fn delimiter(file: &str) -> Option<u8> {
+        let file = file.to_lowercase();
+
+        if file.ends_with(".csv") {
+            Some(b',')
+        } else if file.ends_with(".tsv") {
+            Some(b'\t')
+        } else {
+            None
+        }
+    }
+
+    /// Header detection configured as `csv_header`
+    pub fn header_mode(mode: &str) -> CsvHeader {
+        match mode {
+            "auto" => CsvHeader::Auto,
+            "always" => CsvHeader::Always,
+            "never" => CsvHeader::Never,
+            mode => {
+                let message = format!(
+                    "Unknown `csv_header` \"{}\" (expected \"auto\", \"always\" or \"never\").",
+                    mode
+                );
+                push_message(Type::Error, &message);
+                exit(1)
+            }
+        }
+    }
+
+    /// Render delimited text as an HTML table (without blank lines, so it stays a
+    /// single HTML block when inlined in markdown)
+    pub fn to_html(content: &str, delimiter: u8, file: &str, options: &RenderOptions) -> String {
+        let max_rows = options.csv_max_rows;
+
+        let mut reader = ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(content.as_bytes());
+
+        let mut rows: Vec<Vec<String>> = Vec::new();
+
+        for record in reader.records() {
+            match record {
+                Ok(record) => rows.push(record.iter().map(String::from).collect()),
+                Err(error) => {
+                    let message = format!(
+                        "Failed to parse `{}` as a table, the rows after it are missing: \n\t{}",
+                        file, error
+                    );
+                    push_message(Type::Warning, &message);
+                    break;
+                }
+            }
+        }
+
+        let has_header = match options.csv_header {
+            CsvHeader::Auto => Self::detect_header(&rows),
+            CsvHeader::Always => true,
+            CsvHeader::Never => false,
+        };
+
+        let header = if has_header && !rows.is_empty() {
+            Some(rows.remove(0))
+        } else {
+            None
+        };
+
+        // `0` means no limit
+        let total_rows = rows.len();
+        let truncated = max_rows > 0 && total_rows > max_rows;
+        if truncated {
+            rows.truncate(max_rows);
+
+            let message = format!(
+                "`{}` has {} rows, only the first {} are shown (`csv_max_rows`).",
+                file, total_rows, max_rows
+            );
+            push_message(Type::Warning, &message);
+        }
+
+        let mut html = String::from("<table class=\"csv-table\">\n");
+
+        if let Some(header) = header {
+            html.push_str("<thead><tr>");
+            for (column, cell) in header.iter().enumerate() {
+                // lets a sorting script compare numbers as numbers
+                let data_type = if Self::is_numeric_column(&rows, column) {
+                    "number"
+                } else {
+                    "text"
+                };
+                html.push_str(&format!(
+                    "<th scope=\"col\" data-type=\"{}\">{}</th>",
+                    data_type,
+                    Self::cell(cell)
+                ));
+            }
+            html.push_str("</tr></thead>\n");
+        }
+
+        html.push_str("<tbody>\n");
+        for row in &rows {
+            html.push_str("<tr>");
+            for cell in row {
+                html.push_str(&format!("<td>{}</td>", Self::cell(cell)));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</tbody>\n</table>\n");
+
+        if truncated {
+            html.push_str(&format!(
+                "<p class=\"csv-truncated\">Showing the first {} of {} rows.</p>\n",
+                max_rows, total_rows
+            ));
+        }
+
+        html
+    }
+
+    /// Guess whether the first row is a header: labels sitting above at least one row of data
+    fn detect_header(rows: &[Vec<String>]) -> bool {
+        match rows {
+            [first, _, ..] => first
+                .iter()
+                .all(|cell| !cell.trim().is_empty() && !Self::is_number(cell)),
+            _ => false,
+        }
+    }
+
+    /// Whether every value in a column is a number (empty cells aside)
+    fn is_numeric_column(rows: &[Vec<String>], column: usize) -> bool {
+        let mut values = rows
+            .iter()
+            .filter_map(|row| row.get(column))
+            .filter(|cell| !cell.trim().is_empty())
+            .peekable();
+
+        values.peek().is_some() && values.all(|cell| Self::is_number(cell))
+    }
+
+    fn is_number(cell: &str) -> bool {
+        cell.trim().parse::<f64>().is_ok()
+    }
+
+    /// Escape a cell, line breaks inside quoted cells would end the HTML block
+    fn cell(cell: &str) -> String {
+        encode_text(cell.trim())
+            .replace("\r\n", "<br>")
+            .replace('\n', "<br>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn options() -> RenderOptions {
+        Config::default().render_options()
+    }
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn picks_the_delimiter_from_the_extension() {
+        assert_eq!(Table::delimiter("data.blog.csv"), Some(b','));
+        assert_eq!(Table::delimiter("DATA.TSV"), Some(b'\t'));
+        assert_eq!(Table::delimiter("post.blog.md"), None);
+    }
+
+    #[test]
+    fn detects_headers_above_data() {
+        assert!(Table::detect_header(&rows(&[
+            &["name", "age"],
+            &["Ada", "36"]
+        ])));
+        // numbers or blanks aren't labels
+        assert!(!Table::detect_header(&rows(&[
+            &["2020", "36"],
+            &["2021", "37"]
+        ])));
+        assert!(!Table::detect_header(&rows(&[
+            &["name", ""],
+            &["Ada", "36"]
+        ])));
+        // a single row has nothing to label
+        assert!(!Table::detect_header(&rows(&[&["name", "age"]])));
+    }
+
+    #[test]
+    fn types_numeric_columns() {
+        let rows = rows(&[&["Ada", "36"], &["Alan", ""], &["Grace", "1.5e2"]]);
+
+        assert!(!Table::is_numeric_column(&rows, 0));
+        assert!(Table::is_numeric_column(&rows, 1));
+        assert!(!Table::is_numeric_column(&rows, 2));
+    }
+
+    #[test]
+    fn renders_a_table_with_a_header() {
+        let html = Table::to_html(
+            "name,age\nAda,36\n\"Grace\nHopper\",<85>\n",
+            b',',
+            "people.csv",
+            &options(),
+        );
+
+        assert_eq!(
+            html,
+            "<table class=\"csv-table\">\n\
+             <thead><tr><th scope=\"col\" data-type=\"text\">name</th>\
+             <th scope=\"col\" data-type=\"text\">age</th></tr></thead>\n\
+             <tbody>\n\
+             <tr><td>Ada</td><td>36</td></tr>\n\
+             <tr><td>Grace<br>Hopper</td><td>&lt;85&gt;</td></tr>\n\
+             </tbody>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn renders_ragged_rows_without_a_header() {
+        let html = Table::to_html("1\t2\n3\n", b'\t', "numbers.tsv", &options());
+
+        assert_eq!(
+            html,
+            "<table class=\"csv-table\">\n<tbody>\n\
+             <tr><td>1</td><td>2</td></tr>\n\
+             <tr><td>3</td></tr>\n\
+             </tbody>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn truncates_long_tables() {
+        let options = RenderOptions {
+            csv_max_rows: 3,
+            ..options()
+        };
+        let html = Table::to_html("1\n2\n3\n4\n5\n", b',', "long.csv", &options);
+
+        assert_eq!(html.matches("<tr>").count(), 3);
+        assert!(html.ends_with("<p class=\"csv-truncated\">Showing the first 3 of 5 rows.</p>\n"));
+
+        // `0` means no limit
+        let options = RenderOptions {
+            csv_max_rows: 0,
+            ..options
+        };
+        let html = Table::to_html("1\n2\n3\n4\n5\n", b',', "long.csv", &options);
+
+        assert_eq!(html.matches("<tr>").count(), 5);
+    }
+
+    #[test]
+    fn follows_the_configured_header_mode() {
+        let options = RenderOptions {
+            csv_header: CsvHeader::Never,
+            ..options()
+        };
+        let html = Table::to_html("name,age\nAda,36\n", b',', "people.csv", &options);
+        assert!(!html.contains("<thead>"));
+
+        let options = RenderOptions {
+            csv_header: CsvHeader::Always,
+            ..options
+        };
+        let html = Table::to_html("1,2\n3,4\n", b',', "numbers.csv", &options);
+        assert!(html.contains("<th scope=\"col\" data-type=\"number\">1</th>"));
+    }
+}
@@ -1,6 +1,8 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::Path;
@@ -15,14 +17,14 @@ use futures::executor::block_on;
 
 use crate::{
     cache::Cache,
-    config::Config,
-    gist::GistPage,
+    config::{Config, RenderOptions},
+    gist::{Attachment, GistPage, Revision},
     messages::{push_message, Type},
     parsers::ParserUtils,
 };
 
 // directory to save rendered pages
-static RENDERED_DIR: &'static str = "public";
+static RENDERED_DIR: &str = "public";
 
 pub(crate) struct TemplateWriter;
 
@@ -130,7 +132,28 @@ impl TemplateWriter {
     }
 
     /// Render a list of all gist entries to inject as HTML elements
-    fn render_blog_list(page_map: &HashMap<String, GistPage>) -> String {
+    fn render_blog_list(page_map: &HashMap<String, GistPage>, config: &Config) -> String {
+        let sorted_pages = match config.index_order.as_str() {
+            "date" => ParserUtils::sort_by_date(page_map),
+            // star counts are all `0` without a token, so they can't order anything
+            "stars" if config.github_token.is_none() => {
+                push_message(
+                    Type::Warning,
+                    "`index_order` \"stars\" needs a `github_token`, listing blogs by date instead.",
+                );
+                ParserUtils::sort_by_date(page_map)
+            }
+            "stars" => ParserUtils::sort_by_stars(page_map),
+            order => {
+                let message = format!(
+                    "Unknown `index_order` \"{}\" (expected \"date\" or \"stars\").",
+                    order
+                );
+                push_message(Type::Error, &message);
+                exit(1)
+            }
+        };
+
         // read blog listing template
         let page_list_template = Self::read_file("templates/page_list.html");
 
@@ -139,13 +162,20 @@ impl TemplateWriter {
 
         let handlebars_reg = Handlebars::new();
 
-        for (page, page_data) in page_map {
+        for (page, page_data) in sorted_pages {
             let template_variables = &json!(
                 {
                     "page_title": page_data.title,
                     "page_description": page_data.description,
                     "page_url": format!("/{}", page),
-                    "published_date": page_data.created_at
+                    "published_date": page_data.created_at,
+                    "star_count": page_data.star_count,
+                    "fork_count": page_data.fork_count,
+                    "tags": page_data.tags,
+                    "cover_image": page_data.cover_image,
+                    "word_count": page_data.word_count,
+                    "reading_time": page_data.reading_time,
+                    "excerpt": page_data.excerpt
                 }
             );
 
@@ -166,42 +196,178 @@ impl TemplateWriter {
         page_listing_html
     }
 
-    /// Generate default boilerplate templates
+    /// Generate default boilerplate templates (the missing ones, to not overwrite customizations)
     pub fn generate_boilerplate() {
-        if !Path::new("templates").exists() {
-            Self::prepare("templates");
-
-            let mut default_templates: HashMap<&str, &[u8]> = HashMap::with_capacity(5);
-            default_templates.insert("404.html", include_bytes!("../templates/404.html"));
-            default_templates.insert("comments.html", include_bytes!("../templates/comments.html"));
-            default_templates.insert("index.html", include_bytes!("../templates/index.html"));
-            default_templates.insert("page_list.html", include_bytes!("../templates/page_list.html"));
-            default_templates.insert("page.html", include_bytes!("../templates/page.html"));
-        
-            for (file, content) in default_templates {
-                let mut template = match File::create(format!("templates/{}", file)) {
-                    Ok(file) => file,
-                    Err(error) => {
-                        let message = format!("Failed to create boilerplate template file: \n\t{}", error);
-                        push_message(Type::Error, &message);
-                        exit(1)
-                    }
-                };
-        
-                match template.write_all(content) {
-                    Ok(()) => (),
-                    Err(error) => {
-                        let message = format!("Failed to generate boilerplate template: \n\t{}", error);
-                        push_message(Type::Error, &message);
-                        exit(1)
-                    }
+        Self::prepare("templates");
+
+        let mut default_templates: HashMap<&str, &[u8]> = HashMap::with_capacity(6);
+        default_templates.insert("404.html", include_bytes!("../templates/404.html"));
+        default_templates.insert(
+            "comments.html",
+            include_bytes!("../templates/comments.html"),
+        );
+        default_templates.insert("index.html", include_bytes!("../templates/index.html"));
+        default_templates.insert(
+            "page_list.html",
+            include_bytes!("../templates/page_list.html"),
+        );
+        default_templates.insert("page.html", include_bytes!("../templates/page.html"));
+        default_templates.insert(
+            "revisions.html",
+            include_bytes!("../templates/revisions.html"),
+        );
+
+        for (file, content) in default_templates {
+            let template_path = format!("templates/{}", file);
+
+            if Path::new(&template_path).exists() {
+                continue;
+            }
+
+            let mut template = match File::create(template_path) {
+                Ok(file) => file,
+                Err(error) => {
+                    let message =
+                        format!("Failed to create boilerplate template file: \n\t{}", error);
+                    push_message(Type::Error, &message);
+                    exit(1)
                 }
+            };
+
+            match template.write_all(content) {
+                Ok(()) => (),
+                Err(error) => {
+                    let message = format!("Failed to generate boilerplate template: \n\t{}", error);
+                    push_message(Type::Error, &message);
+                    exit(1)
+                }
+            }
+        }
+    }
+
+    /// Render the comments of a gist entry to inject as HTML elements
+    fn render_comments(
+        comments_template: &str,
+        page_data: &GistPage,
+        show_comments: bool,
+    ) -> String {
+        // local blogs don't have a gist to comment on
+        if page_data.html_url.is_empty() {
+            return String::new();
+        }
+
+        let comment_link = format!(
+            "<br>\n\t<a href=\"{}\" style=\"color: blue;\">{}</a>",
+            page_data.html_url,
+            if show_comments {
+                "Leave a comment on this gist"
+            } else {
+                "Read comments for this gist"
             }
+        );
+
+        if !show_comments {
+            return comment_link;
         }
+
+        let handlebars_reg = Handlebars::new();
+
+        let mut comments_html = String::new();
+
+        for comment in &page_data.comments {
+            let template_variables = &json!(
+                {
+                    "comment_user": comment.user,
+                    "comment_user_url": comment.user_url,
+                    "comment_avatar": comment.avatar_url,
+                    "comment_date": comment.created_at,
+                    "comment_url": comment.html_url,
+                    "comment_content": comment.content
+                }
+            );
+
+            let rendered_comment: String = match handlebars_reg
+                .render_template(comments_template, template_variables)
+            {
+                Ok(html) => html,
+                Err(error) => {
+                    let message = format!("Failed to render Handlebars template: \n\t{}", error);
+                    push_message(Type::Error, &message);
+                    exit(1)
+                }
+            };
+
+            comments_html = format!("{}\n{}", comments_html, rendered_comment);
+        }
+
+        format!("{}\n{}", comments_html, comment_link)
+    }
+
+    /// Attachments of a gist entry which can be saved next to its page
+    fn servable_attachments<'a>(
+        page: &'a str,
+        page_data: &'a GistPage,
+    ) -> impl Iterator<Item = &'a Attachment> {
+        page_data.attachments.iter().filter(move |attachment| {
+            // gist filenames can't have slashes, but local ones end up here too
+            let servable = attachment.file != "index.html"
+                && !attachment.file.starts_with('.')
+                && !attachment.file.contains(['/', '\\']);
+
+            if !servable {
+                let message = format!(
+                    "Attachment `{}` of \"{}\" would clash with the page. (SKIPPED)",
+                    attachment.file, page
+                );
+                push_message(Type::Warning, &message);
+            }
+
+            servable
+        })
+    }
+
+    /// Render the changelog of a gist entry to inject as HTML elements
+    fn render_revisions(revisions_template: &str, revisions: &[Revision]) -> String {
+        let handlebars_reg = Handlebars::new();
+
+        let mut revisions_html = String::new();
+
+        for revision in revisions {
+            let template_variables = &json!(
+                {
+                    "revision_sha": revision.version,
+                    "revision_short_sha": revision.version.chars().take(7).collect::<String>(),
+                    "revision_date": revision.committed_at,
+                    "revision_url": revision.html_url,
+                    "revision_diff": revision.diff,
+                    "additions": revision.additions,
+                    "deletions": revision.deletions
+                }
+            );
+
+            let rendered_revision: String = match handlebars_reg
+                .render_template(revisions_template, template_variables)
+            {
+                Ok(html) => html,
+                Err(error) => {
+                    let message = format!("Failed to render Handlebars template: \n\t{}", error);
+                    push_message(Type::Error, &message);
+                    exit(1)
+                }
+            };
+
+            revisions_html = format!("{}\n{}", revisions_html, rendered_revision);
+        }
+
+        revisions_html
     }
 
     /// Render and build all templates with the boilerplate HTML
-    pub fn render_templates(page_map: &HashMap<String, GistPage>) {
+    pub fn render_templates(
+        page_map: &HashMap<String, GistPage>,
+        options: &RenderOptions,
+        rebuild: bool,
+    ) {
         // prepare the directory to save rendered templates
         Self::prepare(RENDERED_DIR);
 
@@ -210,7 +376,7 @@ impl TemplateWriter {
         let handlebars_reg = Handlebars::new();
 
         // render index page
-        let blog_listing = Self::render_blog_list(&page_map); // generate blog listing
+        let blog_listing = Self::render_blog_list(page_map, &config); // generate blog listing
 
         let template_variables = &json!(
             {
@@ -241,83 +407,127 @@ impl TemplateWriter {
 
         Self::save_file("index.html", index_html.as_bytes(), false);
 
+        // code highlighted with classes is styled by a stylesheet generated from the theme
+        let syntax_css = ParserUtils::syntax_css(options);
+        if let Some(css) = &syntax_css {
+            Self::save_file("syntax.css", css.as_bytes(), false);
+        }
+
         // render pages
         let page_template = Self::read_file("templates/page.html");
+        let revisions_template = Self::read_file("templates/revisions.html");
+        let comments_template = Self::read_file("templates/comments.html");
+
+        // templates picked with `layout` in a blog's metadata block, read once each
+        let mut layout_templates: HashMap<String, String> = HashMap::new();
 
         for (page, page_data) in page_map {
+            // each pages title has to be rendered according to configured formatting
+            let page_title = match handlebars_reg.render_template(
+                &config.pages_title,
+                &json!({
+                    "blog_title": page_data.title
+                }),
+            ) {
+                Ok(rendered_title) => rendered_title,
+                Err(error) => {
+                    let message = format!("Failed to render Handlebars template: \n\t{}", error);
+                    push_message(Type::Error, &message);
+                    exit(1)
+                }
+            };
+
+            let comment_section =
+                Self::render_comments(&comments_template, page_data, config.show_comments);
+
+            let attachments: Vec<&Attachment> =
+                Self::servable_attachments(page, page_data).collect();
+
+            let template_variables = &json!(
+                {
+                    "blog_title": config.blog_title,
+                    "blog_description": config.blog_description,
+                    "blog_url": config.blog_url,
+                    "page_title": page_title,
+                    "post_title": page_data.title,
+                    "page_description": page_data.description,
+                    "page_url": ParserUtils::join_url_path(&config.blog_url, page),
+                    "published_date": page_data.created_at,
+                    "updated_at": page_data.updated_at,
+                    "revision": page_data.revision,
+                    "revisions": Self::render_revisions(&revisions_template, &page_data.revisions),
+                    "blog_contents": page_data.content,
+                    "toc": page_data.toc,
+                    "word_count": page_data.word_count,
+                    "reading_time": page_data.reading_time,
+                    "excerpt": page_data.excerpt,
+                    "syntax_css": syntax_css.as_ref().map(|_| "/syntax.css"),
+                    "comment_section": comment_section,
+                    "star_count": page_data.star_count,
+                    "fork_count": page_data.fork_count,
+                    "attachments": attachments
+                        .iter()
+                        .map(|attachment| json!({
+                            "name": attachment.file,
+                            "url": ParserUtils::attachment_url(page, &attachment.file)
+                        }))
+                        .collect::<Vec<_>>(),
+                    "tags": page_data.tags,
+                    "cover_image": page_data.cover_image,
+                    "extra": page_data.extra
+                }
+            );
+
+            let template = match &page_data.layout {
+                Some(layout) => layout_templates
+                    .entry(layout.to_string())
+                    .or_insert_with(|| Self::read_file(&format!("templates/{}.html", layout))),
+                None => &page_template,
+            };
+
+            // a page is rebuilt when anything that ends up in its output changes
+            let mut hasher = DefaultHasher::new();
+            template_variables.to_string().hash(&mut hasher);
+            template.hash(&mut hasher);
+            config.minify_html.hash(&mut hasher);
+            for attachment in &attachments {
+                attachment.file.hash(&mut hasher);
+                attachment.content.hash(&mut hasher);
+            }
+
             let blog_cache: Cache = Cache {
                 permalink_key: page.to_string(),
-                updated_at: page_data.updated_at.clone(),
+                fingerprint: format!("{:016x}", hasher.finish()),
             };
 
-            match block_on(Cache::is_cached(&blog_cache)) {
-                Ok(true) => {
-                    let message = format!("Skipped entry \"{}\" (exists in disk cache).", page);
-                    push_message(Type::Info, &message);
-                    continue;
+            if let (Ok(true), false) = (block_on(Cache::is_cached(&blog_cache)), rebuild) {
+                let message = format!("Skipped entry \"{}\" (exists in disk cache).", page);
+                push_message(Type::Info, &message);
+                continue;
+            }
+
+            let rendered_page = match handlebars_reg.render_template(template, template_variables) {
+                Ok(rendered_page) => {
+                    if config.minify_html {
+                        Self::minify_html(rendered_page)
+                    } else {
+                        rendered_page
+                    }
                 }
-                _ => {
-                    // each pages title has to be rendered according to configured formatting
-                    let page_title = match handlebars_reg.render_template(
-                        &config.pages_title,
-                        &json!({
-                            "blog_title": page_data.title
-                        }),
-                    ) {
-                        Ok(rendered_title) => rendered_title,
-                        Err(error) => {
-                            let message =
-                                format!("Failed to render Handlebars template: \n\t{}", error);
-                            push_message(Type::Error, &message);
-                            exit(1)
-                        }
-                    };
-
-                    let comment_section = format!(
-                        "<br>\n\t<a href=\"{}\" style=\"color: blue;\">Read comments for this gist</a>",
-                        page_data.html_url
-                    );
+                Err(error) => {
+                    let message = format!("Failed to render Handlebars template: \n\t{}", error);
+                    push_message(Type::Error, &message);
+                    exit(1)
+                }
+            };
 
-                    let template_variables = &json!(
-                        {
-                            "blog_title": config.blog_title,
-                            "blog_description": config.blog_description,
-                            "blog_url": config.blog_url,
-                            "page_title": page_title,
-                            "page_description": page_data.description,
-                            "page_url": ParserUtils::join_url_path(&config.blog_url, page),
-                            "published_date": page_data.created_at,
-                            "updated_at": page_data.updated_at,
-                            "blog_contents": page_data.content,
-                            "comment_section": comment_section
-                        }
-                    );
+            block_on(Cache::save_cache_entry(&blog_cache)).ok();
 
-                    let rendered_page =
-                        match handlebars_reg.render_template(&page_template, template_variables) {
-                            Ok(rendered_page) => {
-                                if config.minify_html {
-                                    Self::minify_html(rendered_page)
-                                } else {
-                                    rendered_page
-                                }
-                            }
-                            Err(error) => {
-                                let message =
-                                    format!("Failed to render Handlebars template: \n\t{}", error);
-                                push_message(Type::Error, &message);
-                                exit(1)
-                            }
-                        };
-
-                    block_on(Cache::save_cache_entry(&Cache {
-                        permalink_key: page.to_string(),
-                        updated_at: page_data.updated_at.clone(),
-                    }))
-                    .ok();
-
-                    Self::save_file(&page, rendered_page.as_bytes(), true)
-                }
+            Self::save_file(page, rendered_page.as_bytes(), true);
+
+            for attachment in attachments {
+                let filename = format!("{}/{}", page, attachment.file);
+                Self::save_file(&filename, &attachment.content, false);
             }
         }
     }
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use pulldown_cmark::{html, Event};
+
+/// A heading listed in the table of contents
+struct TocEntry {
+    level: u32,
+    id: String,
+    text: String,
+}
+
+/// Headings of a blog, to give them unique IDs and build a table of contents
+#[derive(Default)]
+pub(crate) struct Toc {
+    entries: Vec<TocEntry>,
+    ids: HashSet<String>,
+    // self-links next to the headings (`heading_anchors`)
+    anchors: bool,
+}
+
+impl Toc {
+    /// An empty table of contents, optionally with self-links next to the headings
+    pub fn new(anchors: bool) -> Self {
+        Self {
+            anchors,
+            ..Self::default()
+        }
+    }
+
+    /// Render a heading with a unique ID and add it to the table of contents
+    /// (level 1 headings are titles, so they're left out of it)
+    pub fn heading(&mut self, level: u32, content: Vec<Event>) -> String {
+        let text: String = content
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let id = self.unique_id(&Self::slug(&text));
+
+        let mut html = format!(
+            "<h{} id=\"{}\">",
+            level,
+            encode_double_quoted_attribute(&id)
+        );
+        if self.anchors {
+            html.push_str(&format!(
+                "<a class=\"anchor\" href=\"#{}\" aria-hidden=\"true\">#</a>",
+                encode_double_quoted_attribute(&id)
+            ));
+        }
+        html::push_html(&mut html, content.into_iter());
+        html.push_str(&format!("</h{}>\n", level));
+
+        if level > 1 {
+            self.entries.push(TocEntry { level, id, text });
+        }
+
+        html
+    }
+
+    /// GitHub style slug of a heading: lowercase, spaces become dashes and punctuation is dropped
+    fn slug(text: &str) -> String {
+        let slug: String = text
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                ' ' | '-' => Some('-'),
+                '_' => Some('_'),
+                c if c.is_alphanumeric() => Some(c),
+                _ => None,
+            })
+            .collect();
+
+        if slug.is_empty() {
+            String::from("section")
+        } else {
+            slug
+        }
+    }
+
+    /// Suffix an ID already taken by an earlier heading (`usage`, `usage-1`, `usage-2`...)
+    fn unique_id(&mut self, slug: &str) -> String {
+        let mut id = slug.to_string();
+        let mut suffix = 0;
+
+        while self.ids.contains(&id) {
+            suffix += 1;
+            id = format!("{}-{}", slug, suffix);
+        }
+
+        self.ids.insert(id.clone());
+        id
+    }
+
+    /// The headings as nested lists of links (empty without any heading)
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+
+        // levels of the lists that are still open
+        let mut open: Vec<u32> = Vec::new();
+
+        for entry in &self.entries {
+            while open.len() > 1 && entry.level < open[open.len() - 1] {
+                open.pop();
+                html.push_str("</li>\n</ul>\n");
+            }
+
+            match open.last() {
+                None => html.push_str("<ul class=\"toc\">\n<li>"),
+                Some(&level) if entry.level > level => html.push_str("\n<ul>\n<li>"),
+                Some(_) => {
+                    open.pop();
+                    html.push_str("</li>\n<li>");
+                }
+            }
+            open.push(entry.level);
+
+            html.push_str(&format!(
+                "<a href=\"#{}\">{}</a>",
+                encode_double_quoted_attribute(&entry.id),
+                encode_text(&entry.text)
+            ));
+        }
+
+        for _ in open {
+            html.push_str("</li>\n</ul>\n");
+        }
+
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toc(headings: &[(u32, &str)]) -> Toc {
+        let mut toc = Toc::default();
+        for (level, text) in headings {
+            toc.heading(*level, vec![Event::Text((*text).into())]);
+        }
+        toc
+    }
+
+    #[test]
+    fn slugs_headings_like_github() {
+        assert_eq!(Toc::slug("Hello, World!"), "hello-world");
+        assert_eq!(
+            Toc::slug(" snake_case and-dashes "),
+            "snake_case-and-dashes"
+        );
+        assert_eq!(Toc::slug("?!"), "section");
+    }
+
+    #[test]
+    fn suffixes_duplicate_ids() {
+        let mut toc = Toc::default();
+        let first = toc.heading(2, vec![Event::Text("Usage".into())]);
+        let second = toc.heading(2, vec![Event::Text("Usage".into())]);
+        let third = toc.heading(3, vec![Event::Text("Usage".into())]);
+
+        assert!(first.starts_with("<h2 id=\"usage\">"));
+        assert!(second.starts_with("<h2 id=\"usage-1\">"));
+        assert!(third.starts_with("<h3 id=\"usage-2\">"));
+    }
+
+    #[test]
+    fn links_headings_to_themselves_with_anchors() {
+        let heading = Toc::new(true).heading(2, vec![Event::Text("Usage".into())]);
+
+        assert_eq!(
+            heading,
+            "<h2 id=\"usage\"><a class=\"anchor\" href=\"#usage\" aria-hidden=\"true\">#</a>Usage</h2>\n"
+        );
+    }
+
+    #[test]
+    fn leaves_titles_out_of_the_toc() {
+        assert_eq!(toc(&[(1, "Title")]).to_html(), "");
+    }
+
+    #[test]
+    fn nests_deeper_headings() {
+        let toc = toc(&[
+            (1, "Title"),
+            (2, "Install"),
+            (3, "Linux"),
+            (3, "macOS"),
+            (2, "Usage"),
+            (4, "Flags"),
+        ]);
+
+        assert_eq!(
+            toc.to_html(),
+            "<ul class=\"toc\">\n\
+             <li><a href=\"#install\">Install</a>\n\
+             <ul>\n\
+             <li><a href=\"#linux\">Linux</a></li>\n\
+             <li><a href=\"#macos\">macOS</a></li>\n\
+             </ul>\n\
+             </li>\n\
+             <li><a href=\"#usage\">Usage</a>\n\
+             <ul>\n\
+             <li><a href=\"#flags\">Flags</a></li>\n\
+             </ul>\n\
+             </li>\n\
+             </ul>\n"
+        );
+    }
+
+    #[test]
+    fn keeps_shallower_headings_after_a_deep_start_in_one_list() {
+        let toc = toc(&[(3, "Deep"), (2, "Shallow")]);
+
+        assert_eq!(
+            toc.to_html(),
+            "<ul class=\"toc\">\n\
+             <li><a href=\"#deep\">Deep</a></li>\n\
+             <li><a href=\"#shallow\">Shallow</a></li>\n\
+             </ul>\n"
+        );
+    }
+}